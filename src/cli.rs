@@ -0,0 +1,175 @@
+//! the command line interface, the commands except `gui` talk to the running instance by the ipc
+//! socket, `--daemon` runs the instance without a window
+
+use std::io::{self, Read, Write};
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::ipc::{Client, ContentKind, EntryInfo, Request, Response};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "A history clipboard",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    /// Capture the clipboard and keep the history without a window, the gui and the other
+    /// commands talk to it
+    #[arg(long)]
+    pub daemon: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Show the history window, it is the default command
+    Gui,
+    /// List the history entries, newest first
+    List {
+        /// Only list the entries of the type
+        #[arg(long = "type", value_enum)]
+        content_type: Option<ContentType>,
+        /// Print the entries as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the content of the entry
+    Get {
+        /// The entry id printed by `list`
+        id: u64,
+        /// Print the data of the target instead of the content, like text/html
+        #[arg(long)]
+        target: Option<String>,
+    },
+    /// Copy the entry to the clipboard
+    Copy { id: u64 },
+    /// Delete the entry from the history
+    Delete { id: u64 },
+    /// Delete all the entries
+    Clear,
+    /// Add the content read from stdin to the history and copy it to the clipboard
+    Push {
+        /// The target of the content, like text/html or image/png
+        #[arg(long, default_value = "text/plain;charset=utf-8")]
+        target: String,
+    },
+    /// Unlock the history of the daemon, the passphrase is read from stdin
+    Unlock,
+    /// Lock the history of the daemon
+    Lock,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum ContentType {
+    Text,
+    Image,
+}
+
+impl ContentType {
+    fn accept(&self, kind: ContentKind) -> bool {
+        match self {
+            ContentType::Text => matches!(kind, ContentKind::Text | ContentKind::Html),
+            ContentType::Image => kind == ContentKind::Image,
+        }
+    }
+}
+
+/// run the `command` on the running instance
+pub fn run(command: Command) -> Result<()> {
+    let mut client = Client::connect()?;
+
+    match command {
+        Command::Gui => return Err(anyhow!("the gui is not run by the client")),
+
+        Command::List { content_type, json } => {
+            let entries = match client.call(&Request::List, &[])?.0 {
+                Response::Entries { entries } => entries,
+                response => return Err(unexpected(response)),
+            };
+
+            let entries = entries
+                .into_iter()
+                .filter(|entry| {
+                    content_type.is_none_or(|content_type| content_type.accept(entry.kind))
+                })
+                .collect::<Vec<_>>();
+
+            print_entries(&entries, json)?;
+        }
+
+        Command::Get { id, target } => {
+            let data = match client.call(&Request::Get { id, target }, &[])? {
+                (Response::Data { .. }, data) => data,
+                (response, _) => return Err(unexpected(response)),
+            };
+
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&data)?;
+            stdout.flush()?;
+        }
+
+        Command::Copy { id } => expect_done(client.call(&Request::Copy { id }, &[])?.0)?,
+
+        Command::Delete { id } => expect_done(client.call(&Request::Delete { id }, &[])?.0)?,
+
+        Command::Clear => expect_done(client.call(&Request::Clear, &[])?.0)?,
+
+        Command::Push { target } => {
+            let mut data = vec![];
+            io::stdin().lock().read_to_end(&mut data)?;
+
+            expect_done(client.call(&Request::Push { target }, &data)?.0)?
+        }
+
+        Command::Unlock => {
+            let mut passphrase = String::new();
+            io::stdin().read_line(&mut passphrase)?;
+            let passphrase = passphrase.trim_end_matches(['\n', '\r']).to_string();
+
+            // the daemon answers when the storage is unlocked or refuses the passphrase
+            expect_done(client.call(&Request::Unlock { passphrase }, &[])?.0)?
+        }
+
+        Command::Lock => expect_done(client.call(&Request::Lock, &[])?.0)?,
+    }
+
+    Ok(())
+}
+
+fn print_entries(entries: &[EntryInfo], json: bool) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    if json {
+        serde_json::to_writer_pretty(&mut stdout, entries)?;
+        writeln!(stdout)?;
+
+        return Ok(());
+    }
+
+    for entry in entries {
+        let kind = match entry.kind {
+            ContentKind::Text => "text",
+            ContentKind::Html => "html",
+            ContentKind::Files => "files",
+            ContentKind::Image => "image",
+        };
+
+        writeln!(stdout, "{}\t{}\t{}", entry.id, kind, entry.preview)?;
+    }
+
+    Ok(())
+}
+
+fn expect_done(response: Response) -> Result<()> {
+    match response {
+        Response::Done => Ok(()),
+        response => Err(unexpected(response)),
+    }
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    anyhow!("unexpected response {:?}", response)
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+
+use super::{Selection, Target};
+
+/// the owner of a selection content, the content copied again has a new owner
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Owner {
+    /// the window or the offer of the owner
+    pub id: u64,
+    /// when the owner took the selection
+    pub timestamp: u64,
+}
+
+/// the system clipboard access, the history logic of [`Clipboard`](super::Clipboard) is written
+/// against it
+pub trait Backend: Send {
+    /// the receiver of the changed selections, a selection is sent when its owner changed by
+    /// other app, the changes made by [`Backend::store`] should not be sent
+    fn changes(&self) -> Receiver<Selection>;
+
+    /// the owner of the current selection content, None means the backend can't tell the owners
+    /// apart, then the contents are compared instead
+    fn owner(&mut self, _selection: Selection) -> Result<Option<Owner>> {
+        Ok(None)
+    }
+
+    /// load the targets the selection owner offers
+    fn load_targets(&mut self, selection: Selection) -> Result<Vec<String>>;
+
+    /// load the selection content as `target`, None means the owner can't convert the content
+    /// to `target`
+    fn load(&mut self, selection: Selection, target: &str) -> Result<Option<Vec<u8>>>;
+
+    /// take the selection ownership and serve the `targets`
+    fn store(&mut self, selection: Selection, targets: Arc<[Target]>) -> Result<()>;
+
+    /// the receiver of the selections which the owner asks to save before it exits, by the
+    /// freedesktop clipboard manager protocol, [`Backend::finish_save`] must be called after
+    /// the selection is stored
+    fn save_requests(&self) -> Receiver<Selection> {
+        crossbeam_channel::never()
+    }
+
+    /// tell the owner whether the selection is saved
+    fn finish_save(&mut self, _selection: Selection, _saved: bool) {}
+
+    /// the receiver of the global hotkey presses, with the pointer position on the screen
+    fn hotkey_presses(&self) -> Receiver<(f64, f64)> {
+        crossbeam_channel::never()
+    }
+}
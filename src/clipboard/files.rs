@@ -0,0 +1,130 @@
+//! the copied files formats, the text/uri-list is the standard one, and the file managers
+//! use x-special/gnome-copied-files to tell copying from cutting
+
+use std::path::Path;
+use std::sync::Arc;
+
+use druid::Data;
+
+pub const URI_LIST_TARGET: &str = "text/uri-list";
+pub const GNOME_FILES_TARGET: &str = "x-special/gnome-copied-files";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Data)]
+pub enum FileOperation {
+    Copy,
+    Cut,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ContentFile {
+    pub uri: Arc<str>,
+    /// the decoded last segment of the uri
+    pub name: Arc<str>,
+    pub is_dir: bool,
+}
+
+impl ContentFile {
+    pub fn new(uri: &str) -> Self {
+        let path = uri.strip_prefix("file://").map(percent_decode);
+        let is_dir = path.is_some_and(|path| Path::new(&path).is_dir());
+
+        let name = uri
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .map(percent_decode)
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| uri.to_string());
+
+        Self {
+            uri: uri.into(),
+            name: name.into(),
+            is_dir,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ContentFiles {
+    pub operation: FileOperation,
+    pub files: Arc<[ContentFile]>,
+}
+
+impl ContentFiles {
+    /// parse the `data` of the files `target`, None means it has no file
+    pub fn parse(target: &str, data: &[u8]) -> Option<Self> {
+        let data = String::from_utf8_lossy(data);
+        let mut lines = data
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let operation = match target {
+            GNOME_FILES_TARGET => match lines.next()? {
+                "copy" => FileOperation::Copy,
+                "cut" => FileOperation::Cut,
+                _ => return None,
+            },
+
+            URI_LIST_TARGET => FileOperation::Copy,
+
+            _ => return None,
+        };
+
+        let files = lines.map(ContentFile::new).collect::<Vec<_>>();
+        if files.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            operation,
+            files: files.into(),
+        })
+    }
+
+    pub fn to_uri_list(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| format!("{}\r\n", file.uri))
+            .collect()
+    }
+
+    pub fn to_gnome_copied_files(&self) -> String {
+        let operation = match self.operation {
+            FileOperation::Copy => "copy",
+            FileOperation::Cut => "cut",
+        };
+
+        let mut lines = vec![operation];
+        lines.extend(self.files.iter().map(|file| file.uri.as_ref()));
+
+        lines.join("\n")
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,204 @@
+//! a tiny html reader, it only understands the inline styles we can show in the preview, the
+//! rest tags are dropped
+
+/// a piece of text with the same style
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HtmlSpan {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub heading: bool,
+}
+
+/// the tags which break the line
+const BLOCK_TAGS: &[&str] = &[
+    "br",
+    "p",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+    "blockquote",
+];
+/// the tags whose content is not shown
+const HIDDEN_TAGS: &[&str] = &["head", "title", "script", "style"];
+
+#[derive(Debug, Default)]
+struct Depth {
+    bold: usize,
+    italic: usize,
+    underline: usize,
+    heading: usize,
+    hidden: usize,
+}
+
+#[derive(Debug, Default)]
+struct Reader {
+    spans: Vec<HtmlSpan>,
+    depth: Depth,
+    /// the text of the current style
+    text: String,
+    /// the last char pushed to any span
+    last: Option<char>,
+}
+
+impl Reader {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.last = Some(c);
+    }
+
+    /// push the html `chunk`, the whitespaces are collapsed and the entities are decoded
+    fn push_chunk(&mut self, chunk: &str) {
+        if self.depth.hidden > 0 {
+            return;
+        }
+
+        let mut rest = chunk;
+
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                if !matches!(self.last, None | Some(' ' | '\n')) {
+                    self.push(' ');
+                }
+                rest = &rest[c.len_utf8()..];
+
+                continue;
+            }
+
+            if c == '&' {
+                if let Some(end) = rest.find(';').filter(|end| *end <= 10) {
+                    if let Some(decoded) = decode_entity(&rest[1..end]) {
+                        self.push(decoded);
+                        rest = &rest[end + 1..];
+
+                        continue;
+                    }
+                }
+            }
+
+            self.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    fn push_tag(&mut self, tag: &str) {
+        let (name, closing) = tag_name(tag);
+
+        if BLOCK_TAGS.contains(&name.as_str()) && !matches!(self.last, None | Some('\n')) {
+            self.push('\n');
+        }
+
+        // the self closing tags like <br/> don't change the style
+        if tag.ends_with("/>") {
+            return;
+        }
+
+        self.flush();
+
+        let depth = &mut self.depth;
+        let counter = match name.as_str() {
+            "b" | "strong" => &mut depth.bold,
+            "i" | "em" => &mut depth.italic,
+            "u" | "ins" => &mut depth.underline,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => &mut depth.heading,
+            name if HIDDEN_TAGS.contains(&name) => &mut depth.hidden,
+            _ => return,
+        };
+
+        if closing {
+            *counter = counter.saturating_sub(1);
+        } else {
+            *counter += 1;
+        }
+    }
+
+    /// finish the span of the current style
+    fn flush(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+
+        self.spans.push(HtmlSpan {
+            text: std::mem::take(&mut self.text),
+            bold: self.depth.bold > 0,
+            italic: self.depth.italic > 0,
+            underline: self.depth.underline > 0,
+            heading: self.depth.heading > 0,
+        });
+    }
+}
+
+/// split the `html` into styled spans
+pub fn parse(html: &str) -> Vec<HtmlSpan> {
+    let mut reader = Reader::default();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..]
+            .find('>')
+            .map_or(rest.len(), |end| start + end + 1);
+
+        reader.push_chunk(&rest[..start]);
+        reader.push_tag(&rest[start..end]);
+
+        rest = &rest[end..];
+    }
+
+    reader.push_chunk(rest);
+    reader.flush();
+
+    reader.spans
+}
+
+/// strip the `html` down to the plain text
+pub fn to_text(html: &str) -> String {
+    parse(html)
+        .into_iter()
+        .map(|span| span.text)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// get the lowercase name of the `tag` and whether it is a closing tag
+fn tag_name(tag: &str) -> (String, bool) {
+    let tag = tag.trim_start_matches('<').trim_end_matches('>');
+    let closing = tag.starts_with('/');
+
+    let name = tag
+        .trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    (name, closing)
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+
+            char::from_u32(code)
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! the image formats, the image is captured from any supported target, and encoded to the other
+//! formats when they are requested after restoring
+
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use tap::TapFallible;
+use tracing::error;
+
+/// the image targets we can decode, ordered by preference, the lossless formats go first
+pub const IMAGE_TARGETS: &[(&str, ImageFormat)] = &[
+    ("image/png", ImageFormat::Png),
+    ("image/tiff", ImageFormat::Tiff),
+    ("image/bmp", ImageFormat::Bmp),
+    ("image/x-bmp", ImageFormat::Bmp),
+    ("image/webp", ImageFormat::WebP),
+    ("image/gif", ImageFormat::Gif),
+    ("image/jpeg", ImageFormat::Jpeg),
+    ("image/jpg", ImageFormat::Jpeg),
+];
+
+/// the encodings offered when restoring an image
+pub const RESTORE_TARGETS: &[&str] = &["image/png", "image/bmp", "image/jpeg"];
+
+/// the preferred target name of the `format`
+pub fn target_of(format: ImageFormat) -> Option<&'static str> {
+    IMAGE_TARGETS
+        .iter()
+        .find_map(|(name, target_format)| (*target_format == format).then_some(*name))
+}
+
+/// decode the `raw` image and encode it as `target`
+pub fn convert(raw: &[u8], format: ImageFormat, target: &str) -> Option<Vec<u8>> {
+    let image = image::load_from_memory_with_format(raw, format)
+        .tap_err(|err| error!(%err, ?format, "decode image failed"))
+        .ok()?;
+
+    let (image, output_format) = match target {
+        "image/png" => (image, ImageOutputFormat::Png),
+        "image/bmp" => (image, ImageOutputFormat::Bmp),
+        // jpeg has no alpha channel
+        "image/jpeg" => (
+            DynamicImage::ImageRgb8(image.to_rgb8()),
+            ImageOutputFormat::Jpeg(90),
+        ),
+
+        _ => return None,
+    };
+
+    let mut data = vec![];
+    image
+        .write_to(&mut data, output_format)
+        .tap_err(|err| error!(%err, target, "encode image failed"))
+        .ok()?;
+
+    Some(data)
+}
@@ -0,0 +1,163 @@
+//! an in-memory fake backend, it makes the history logic testable without a display server
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
+
+use super::backend::{Backend, Owner};
+use super::{Selection, Target, TargetData};
+
+/// selection -> [(target, data)]
+type Selections = Arc<Mutex<HashMap<Selection, Vec<(String, TargetData)>>>>;
+/// the selections stored by the backend
+type Stored = Arc<Mutex<HashSet<Selection>>>;
+/// selection -> the serial of the copy
+type Owners = Arc<Mutex<HashMap<Selection, u64>>>;
+
+pub struct MemoryBackend {
+    selections: Selections,
+    stored: Stored,
+    /// None when the owners are unknown, like the polled X11 selections
+    owners: Option<Owners>,
+    changes: Receiver<Selection>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> (Self, MemoryHandle) {
+        Self::with_owners(true)
+    }
+
+    /// the backend which can't tell the owners apart
+    pub fn without_owners() -> (Self, MemoryHandle) {
+        Self::with_owners(false)
+    }
+
+    fn with_owners(known: bool) -> (Self, MemoryHandle) {
+        let selections = Selections::default();
+        let stored = Stored::default();
+        let owners = Owners::default();
+        let (change_sender, changes) = crossbeam_channel::unbounded();
+
+        let handle = MemoryHandle {
+            selections: selections.clone(),
+            stored: stored.clone(),
+            owners: owners.clone(),
+            last_serial: Default::default(),
+            change_sender,
+        };
+
+        (
+            Self {
+                selections,
+                stored,
+                owners: known.then_some(owners),
+                changes,
+            },
+            handle,
+        )
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn changes(&self) -> Receiver<Selection> {
+        self.changes.clone()
+    }
+
+    fn owner(&mut self, selection: Selection) -> Result<Option<Owner>> {
+        Ok(self.owners.as_ref().and_then(|owners| {
+            owners.lock().unwrap().get(&selection).map(|serial| Owner {
+                id: *serial,
+                timestamp: 0,
+            })
+        }))
+    }
+
+    fn load_targets(&mut self, selection: Selection) -> Result<Vec<String>> {
+        Ok(self
+            .selections
+            .lock()
+            .unwrap()
+            .get(&selection)
+            .map(|targets| targets.iter().map(|(target, _)| target.clone()).collect())
+            .unwrap_or_default())
+    }
+
+    fn load(&mut self, selection: Selection, target: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .selections
+            .lock()
+            .unwrap()
+            .get(&selection)
+            .and_then(|targets| targets.iter().find(|(name, _)| name == target))
+            .map(|(_, data)| data.get().to_vec()))
+    }
+
+    fn store(&mut self, selection: Selection, targets: Arc<[Target]>) -> Result<()> {
+        let targets = targets
+            .iter()
+            .map(|target| (target.name.clone(), target.data.clone()))
+            .collect();
+
+        self.selections.lock().unwrap().insert(selection, targets);
+        self.stored.lock().unwrap().insert(selection);
+        // our own content has no other owner
+        if let Some(owners) = &self.owners {
+            owners.lock().unwrap().remove(&selection);
+        }
+
+        Ok(())
+    }
+}
+
+/// emulate the other apps which use the selections
+#[derive(Clone)]
+pub struct MemoryHandle {
+    selections: Selections,
+    stored: Stored,
+    owners: Owners,
+    last_serial: Arc<AtomicU64>,
+    change_sender: Sender<Selection>,
+}
+
+impl MemoryHandle {
+    /// copy the `targets` to the selection like other app does
+    pub fn copy(&self, selection: Selection, targets: &[(&str, &[u8])]) {
+        let targets = targets
+            .iter()
+            .map(|(target, data)| (target.to_string(), TargetData::from(*data)))
+            .collect();
+
+        self.selections.lock().unwrap().insert(selection, targets);
+        self.stored.lock().unwrap().remove(&selection);
+        let serial = self.last_serial.fetch_add(1, Ordering::Relaxed) + 1;
+        self.owners.lock().unwrap().insert(selection, serial);
+
+        self.change_sender.send(selection).unwrap();
+    }
+
+    /// report the selection changed without copying, like the polled selections
+    pub fn notify(&self, selection: Selection) {
+        self.change_sender.send(selection).unwrap();
+    }
+
+    /// exit like the app which copied the selection, the selection is cleared unless the
+    /// backend stored it
+    pub fn close(&self, selection: Selection) {
+        if !self.stored.lock().unwrap().contains(&selection) {
+            self.selections.lock().unwrap().remove(&selection);
+        }
+    }
+
+    /// paste the selection content as `target` like other app does
+    pub fn paste(&self, selection: Selection, target: &str) -> Option<Arc<[u8]>> {
+        self.selections
+            .lock()
+            .unwrap()
+            .get(&selection)
+            .and_then(|targets| targets.iter().find(|(name, _)| name == target))
+            .map(|(_, data)| data.get())
+    }
+}
@@ -1,35 +1,180 @@
-use std::io;
-use std::sync::Arc;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
-use crossbeam_channel::{Receiver, Sender};
-use druid::piet::TextStorage;
+use crossbeam_channel::{select, Receiver, Sender};
+use druid::im::Vector;
 use druid::{Data, ImageBuf};
-use image::io::Reader;
 use image::{ImageError, ImageFormat};
 use md5::digest::FixedOutput;
 use md5::{Digest, Md5};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use tap::TapFallible;
-use tracing::{debug, error};
-use x11_clipboard::error::Error;
-use x11_clipboard::xcb::x::{Atom, InternAtom};
+use tracing::{debug, error, warn};
 
-const PNG_ATOM: &str = "image/png";
+pub use self::backend::{Backend, Owner};
+pub use self::files::{ContentFile, ContentFiles, FileOperation};
+pub use self::html::HtmlSpan;
+pub use self::wayland::WaylandBackend;
+pub use self::x11::{watch_hotkey, X11Backend};
+use crate::config::Hotkey;
+
+mod backend;
+mod files;
+mod html;
+mod images;
+#[cfg(test)]
+mod memory;
+mod wayland;
+mod x11;
+mod xfixes;
+
+const TEXT_TARGET: &str = "UTF8_STRING";
+/// the text targets we can load, ordered by preference
+const TEXT_TARGETS: &[&str] = &[TEXT_TARGET, "text/plain;charset=utf-8"];
+/// the targets offered when restoring a text without the captured targets, the apps don't agree
+/// on the text target name
+const TEXT_ALIAS_TARGETS: &[&str] = &[
+    TEXT_TARGET,
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "TEXT",
+    "STRING",
+];
+const HTML_TARGET: &str = "text/html";
+/// the targets describe the selection itself rather than the content
+const META_TARGETS: &[&str] = &[
+    "TARGETS",
+    "TIMESTAMP",
+    "MULTIPLE",
+    "SAVE_TARGETS",
+    "DELETE",
+    "INSERT_SELECTION",
+    "INSERT_PROPERTY",
+];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Data, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    Clipboard,
+    Primary,
+    Secondary,
+}
 
 #[derive(Debug, Clone)]
 pub struct ContentImage {
     pub raw: Arc<[u8]>,
+    /// the format of the raw image
+    pub format: ImageFormat,
     pub image_buf: ImageBuf,
     pub sum: [u8; 16],
 }
 
+impl ContentImage {
+    /// decode the `raw` image, the `sum` is its md5
+    pub fn decode(raw: Arc<[u8]>, format: ImageFormat, sum: [u8; 16]) -> Result<Self, ImageError> {
+        let image = image::load_from_memory_with_format(&raw, format)?;
+        let image_buf = ImageBuf::from_dynamic_image(image);
+
+        Ok(Self {
+            raw,
+            format,
+            image_buf,
+            sum,
+        })
+    }
+
+    /// the targets of the other encodings which are not `offered`, they are encoded when
+    /// requested
+    pub fn converted_targets(&self, offered: &[Target]) -> Vec<Target> {
+        images::RESTORE_TARGETS
+            .iter()
+            .filter(|name| offered.iter().all(|target| target.name != **name))
+            .map(|name| {
+                let raw = self.raw.clone();
+                let format = self.format;
+
+                Target {
+                    name: name.to_string(),
+                    data: TargetData::lazy(move || images::convert(&raw, format, name)),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentHtml {
+    pub html: Arc<str>,
+    /// the plain text fallback, it is offered by the selection owner or stripped from the html
+    pub text: Arc<str>,
+}
+
+impl ContentHtml {
+    fn new(html: Arc<str>, text: Option<Arc<str>>) -> Self {
+        let text = text.unwrap_or_else(|| html::to_text(&html).into());
+
+        Self { html, text }
+    }
+
+    /// the styled spans to show the formatted preview
+    pub fn spans(&self) -> Vec<HtmlSpan> {
+        html::parse(&self.html)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Content {
     Text(Arc<str>),
+    Html(ContentHtml),
+    Files(ContentFiles),
     Image(ContentImage),
 }
 
+impl Content {
+    /// the content of the `data` in the `target` format, None means the target is not supported
+    /// or the data is invalid
+    pub fn from_data(target: &str, data: Vec<u8>) -> Option<Self> {
+        match target {
+            HTML_TARGET => String::from_utf8(data)
+                .ok()
+                .map(|html| Content::Html(ContentHtml::new(html.into(), None))),
+
+            files::URI_LIST_TARGET | files::GNOME_FILES_TARGET => {
+                ContentFiles::parse(target, &data).map(Content::Files)
+            }
+
+            target if TEXT_ALIAS_TARGETS.contains(&target) => {
+                String::from_utf8(data).ok().map(Content::from)
+            }
+
+            target if target.starts_with("image/") => Content::try_from(data).ok(),
+
+            _ => None,
+        }
+    }
+
+    /// the content in its own format, with the target name of the format
+    pub fn to_data(&self) -> (&'static str, Vec<u8>) {
+        match self {
+            Content::Text(text) => ("text/plain;charset=utf-8", text.as_bytes().to_vec()),
+            Content::Html(html) => (HTML_TARGET, html.html.as_bytes().to_vec()),
+            Content::Files(files) => (files::URI_LIST_TARGET, files.to_uri_list().into_bytes()),
+            Content::Image(img) => (
+                images::target_of(img.format).unwrap_or("image"),
+                img.raw.to_vec(),
+            ),
+        }
+    }
+}
+
 impl From<String> for Content {
     fn from(text: String) -> Self {
         Content::Text(text.into())
@@ -40,22 +185,14 @@ impl TryFrom<Vec<u8>> for Content {
     type Error = ImageError;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        let value: Arc<[u8]> = value.into();
-
-        let image =
-            Reader::with_format(io::Cursor::new(value.as_ref()), ImageFormat::Png).decode()?;
-
-        let image_buf = ImageBuf::from_dynamic_image(image);
+        let format = image::guess_format(&value)?;
+        let sum = md5_sum(&value);
 
-        let mut hasher = Md5::default();
-        hasher.update(&value);
-        let sum = *hasher.finalize_fixed().as_mut();
-
-        Ok(Content::Image(ContentImage {
-            raw: value,
-            image_buf,
+        Ok(Content::Image(ContentImage::decode(
+            value.into(),
+            format,
             sum,
-        }))
+        )?))
     }
 }
 
@@ -63,6 +200,8 @@ impl Data for Content {
     fn same(&self, other: &Self) -> bool {
         match (self, other) {
             (Content::Text(text1), Content::Text(text2)) => text1 == text2,
+            (Content::Html(html1), Content::Html(html2)) => html1.html == html2.html,
+            (Content::Files(files1), Content::Files(files2)) => files1 == files2,
             (Content::Image(img1), Content::Image(img2)) => img1.sum == img2.sum,
 
             _ => false,
@@ -70,209 +209,1333 @@ impl Data for Content {
     }
 }
 
+/// a target the selection owner offers and its content
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    pub data: TargetData,
+}
+
+/// the content of a target, the converted content is created when it is requested first time
+#[derive(Clone)]
+pub struct TargetData {
+    data: Arc<OnceLock<Arc<[u8]>>>,
+    convert: Option<Arc<dyn Fn() -> Option<Vec<u8>> + Send + Sync>>,
+}
+
+impl TargetData {
+    /// create the content by `convert` when it is requested, None means convert failed
+    pub fn lazy(convert: impl Fn() -> Option<Vec<u8>> + Send + Sync + 'static) -> Self {
+        Self {
+            data: Default::default(),
+            convert: Some(Arc::new(convert)),
+        }
+    }
+
+    /// whether the content is converted from another one, it needn't be saved
+    pub fn is_converted(&self) -> bool {
+        self.convert.is_some()
+    }
+
+    /// get the content, it is empty when the conversion failed
+    pub fn get(&self) -> Arc<[u8]> {
+        self.data
+            .get_or_init(|| {
+                self.convert
+                    .as_ref()
+                    .and_then(|convert| convert())
+                    .unwrap_or_default()
+                    .into()
+            })
+            .clone()
+    }
+}
+
+impl From<Arc<[u8]>> for TargetData {
+    fn from(data: Arc<[u8]>) -> Self {
+        Self {
+            data: Arc::new(OnceLock::from(data)),
+            convert: None,
+        }
+    }
+}
+
+impl From<&[u8]> for TargetData {
+    fn from(data: &[u8]) -> Self {
+        Arc::<[u8]>::from(data).into()
+    }
+}
+
+impl fmt::Debug for TargetData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.data.get() {
+            None => f.write_str("TargetData(<not converted>)"),
+            Some(data) => write!(f, "TargetData({} bytes)", data.len()),
+        }
+    }
+}
+
+/// a content captured from the selection, with its history record
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// the unique id, it is kept when the history is saved
+    pub id: u64,
+    /// the selection the content is copied from
+    pub selection: Selection,
+    pub content: Content,
+    /// all targets offered by the selection owner, they are offered back when restoring
+    pub targets: Arc<[Target]>,
+    /// the pinned entry is never dropped for the history size
+    pub pinned: bool,
+    /// the md5 sum of the content in its own format
+    pub sum: [u8; 16],
+    /// the size of the content in its own format
+    pub size: usize,
+    /// when the content is copied first, in milliseconds since the unix epoch
+    pub copied_at: u64,
+    /// when the content is copied or restored last, in milliseconds since the unix epoch
+    pub used_at: u64,
+    /// how many times the content is copied or restored
+    pub use_count: u32,
+}
+
+impl Data for Entry {
+    /// the id and the content sum identify the entry, the others are its changing state
+    fn same(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.sum == other.sum
+            && self.pinned == other.pinned
+            && self.used_at == other.used_at
+            && self.use_count == other.use_count
+    }
+}
+
+impl Entry {
+    /// create a new entry of the `content` copied just now
+    pub fn new(selection: Selection, content: Content, targets: Arc<[Target]>) -> Self {
+        let (sum, size) = match &content {
+            Content::Image(img) => (img.sum, img.raw.len()),
+            content => {
+                let (_, data) = content.to_data();
+
+                (md5_sum(&data), data.len())
+            }
+        };
+        let now = now_millis();
+
+        Self {
+            id: new_entry_id(),
+            selection,
+            content,
+            targets,
+            pinned: false,
+            sum,
+            size,
+            copied_at: now,
+            used_at: now,
+            use_count: 1,
+        }
+    }
+
+    /// create an entry which offers the targets derived from the `content`
+    pub fn from_content(selection: Selection, content: Content) -> Self {
+        let targets = match &content {
+            Content::Text(text) => text_targets(text),
+
+            Content::Html(html) => {
+                let mut targets = vec![Target {
+                    name: HTML_TARGET.to_string(),
+                    data: html.html.as_bytes().into(),
+                }];
+                targets.extend(text_targets(&html.text));
+
+                targets
+            }
+
+            Content::Files(files) => vec![
+                Target {
+                    name: files::URI_LIST_TARGET.to_string(),
+                    data: files.to_uri_list().as_bytes().into(),
+                },
+                Target {
+                    name: files::GNOME_FILES_TARGET.to_string(),
+                    data: files.to_gnome_copied_files().as_bytes().into(),
+                },
+            ],
+
+            Content::Image(img) => {
+                let mut targets = images::target_of(img.format)
+                    .map(|name| Target {
+                        name: name.to_string(),
+                        data: img.raw.clone().into(),
+                    })
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                targets.extend(img.converted_targets(&targets));
+
+                targets
+            }
+        };
+
+        Self::new(selection, content, targets.into())
+    }
+
+    /// the entries of the same content are the duplicates in the history
+    pub fn same_content(&self, other: &Self) -> bool {
+        self.sum == other.sum
+            && mem::discriminant(&self.content) == mem::discriminant(&other.content)
+    }
+
+    /// the entry which only offers the plain text of the content, None means the content has no
+    /// text
+    pub fn to_plain_text(&self) -> Option<Self> {
+        let text = match &self.content {
+            Content::Text(text) => text.clone(),
+            Content::Html(html) => html.text.clone(),
+            Content::Files(_) | Content::Image(_) => return None,
+        };
+
+        Some(Self::from_content(self.selection, Content::Text(text)))
+    }
+}
+
+/// add the new `entry` to the front of the history `contents`, the entry of the same content is
+/// moved to the front instead, it keeps its id and counts the use
+pub fn push_history(contents: &mut Vector<Entry>, entry: Entry, max_size: usize) {
+    let entry = match contents.iter().position(|old| old.same_content(&entry)) {
+        Some(index) => {
+            let mut old = contents.remove(index);
+            old.used_at = entry.used_at;
+            old.use_count += 1;
+
+            old
+        }
+
+        None => entry,
+    };
+
+    contents.push_front(entry);
+    truncate_history(contents, max_size);
+}
+
+/// add the `saved` entries after the newer history `contents`, the saved entry of the same
+/// content is merged into the newer one, it keeps the saved id and pin and counts both uses
+pub fn merge_history(contents: &mut Vector<Entry>, saved: Vec<Entry>, max_size: usize) {
+    for entry in saved {
+        match contents.iter_mut().find(|newer| newer.same_content(&entry)) {
+            Some(newer) => {
+                newer.id = entry.id;
+                newer.copied_at = newer.copied_at.min(entry.copied_at);
+                newer.used_at = newer.used_at.max(entry.used_at);
+                newer.use_count += entry.use_count;
+                newer.pinned |= entry.pinned;
+            }
+
+            None => contents.push_back(entry),
+        }
+    }
+
+    truncate_history(contents, max_size);
+}
+
+/// drop the oldest entries beyond `max_size` from the history `contents`, the pinned entries are
+/// kept and not counted
+pub fn truncate_history(contents: &mut Vector<Entry>, max_size: usize) {
+    let mut unpinned = contents.iter().filter(|entry| !entry.pinned).count();
+    let mut index = contents.len();
+
+    while unpinned > max_size && index > 0 {
+        index -= 1;
+
+        if !contents[index].pinned {
+            contents.remove(index);
+            unpinned -= 1;
+        }
+    }
+}
+
+/// a new unique entry id, it is the current time in nanoseconds, or the last id plus one when
+/// the time doesn't go forward
+pub fn new_entry_id() -> u64 {
+    static LAST_ID: AtomicU64 = AtomicU64::new(0);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let last = LAST_ID
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+
+    now.max(last + 1)
+}
+
+/// the current time in milliseconds since the unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+pub fn md5_sum(data: &[u8]) -> [u8; 16] {
+    let mut hasher = Md5::new();
+    hasher.update(data);
+
+    *hasher.finalize_fixed().as_mut()
+}
+
+/// the text targets with all aliases
+fn text_targets(text: &str) -> Vec<Target> {
+    TEXT_ALIAS_TARGETS
+        .iter()
+        .map(|name| Target {
+            name: name.to_string(),
+            data: text.as_bytes().into(),
+        })
+        .collect()
+}
+
+/// the size limits of the captured targets
+#[derive(Debug, Copy, Clone)]
+pub struct Limits {
+    /// the target larger than it is dropped
+    pub max_target_size: usize,
+    /// the targets are dropped when the total size exceeds it
+    pub max_total_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_target_size: 32 * 1024 * 1024,
+            max_total_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// the contents which are not captured, like the passwords
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// ignore the contents offering any of the targets, the password managers offer
+    /// `x-kde-passwordManagerHint`
+    pub targets: Vec<String>,
+    /// ignore the texts matching any of the patterns
+    pub text_patterns: Vec<Regex>,
+}
+
+impl IgnoreRules {
+    fn ignore_targets(&self, names: &[String]) -> bool {
+        names.iter().any(|name| self.targets.contains(name))
+    }
+
+    fn ignore_content(&self, content: &Content) -> bool {
+        let text = match content {
+            Content::Text(text) => text,
+            Content::Html(html) => &html.text,
+            Content::Files(_) | Content::Image(_) => return false,
+        };
+
+        self.text_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(text))
+    }
+}
+
+/// the entries removed from the history, the clipboard forgets their contents, so they are
+/// captured again when they are copied again
+#[derive(Debug, Clone)]
+pub enum Removed {
+    Entry(Entry),
+    All,
+}
+
+/// the current content of a selection, a change is a new content when its owner is changed, or
+/// when its content is changed if the owner is unknown
+#[derive(Debug, Default)]
+struct SelectionState {
+    /// None when the backend can't tell it, or we are the owner
+    owner: Option<Owner>,
+    /// None when the content is not supported or ignored
+    content: Option<Content>,
+    /// the targets of the content, they are served when we keep the selection
+    targets: Option<Arc<[Target]>>,
+}
+
+impl SelectionState {
+    /// forget the current content when it is the `content`, so it is captured again, the
+    /// selection is still kept
+    fn forget(&mut self, content: &Content) {
+        if self
+            .content
+            .as_ref()
+            .is_some_and(|current| current.same(content))
+        {
+            self.forget_all();
+        }
+    }
+
+    fn forget_all(&mut self) {
+        self.owner = None;
+        self.content = None;
+    }
+
+    /// take the `content` of the `owner` as the current one, return true if it is a new content.
+    /// the targets of the last content are dropped unless it is the same one, so they are not
+    /// kept in place of the new content
+    fn update(&mut self, owner: Option<Owner>, content: Option<&Content>) -> bool {
+        let changed = content.is_some_and(|content| {
+            owner.is_some()
+                || !self
+                    .content
+                    .as_ref()
+                    .is_some_and(|current| current.same(content))
+        });
+
+        if changed || content.is_none() {
+            self.targets = None;
+        }
+        self.owner = owner;
+        self.content = content.cloned();
+
+        changed
+    }
+}
+
+/// create the backend of the current session, the wayland backend is preferred when the
+/// WAYLAND_DISPLAY is set, but if the compositor doesn't support the data control protocol, the
+/// X11 backend is used through the XWayland
+/// the `hotkey` is grabbed by the X11 backend, the wayland compositor doesn't allow it
+pub fn new_backend(
+    selections: &[Selection],
+    poll_interval: Duration,
+    load_timeout: Duration,
+    hotkey: Option<&Hotkey>,
+) -> Result<Box<dyn Backend>> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        match WaylandBackend::new(selections, load_timeout) {
+            Ok(backend) => {
+                if hotkey.is_some() {
+                    warn!("the hotkey is not supported on wayland, bind it in the compositor");
+                }
+
+                return Ok(Box::new(backend));
+            }
+            Err(err) => warn!(%err, "create wayland backend failed, fallback to x11"),
+        }
+    }
+
+    Ok(Box::new(X11Backend::new(
+        selections,
+        poll_interval,
+        load_timeout,
+        hotkey,
+    )?))
+}
+
 pub struct Clipboard {
-    x11_clipboard: x11_clipboard::Clipboard,
-    png_atom: Atom,
+    backend: Box<dyn Backend>,
+    limits: Limits,
+    ignore: IgnoreRules,
 
-    content_sender: Sender<Content>,
-    new_content_receiver: Receiver<Content>,
+    content_sender: Sender<Entry>,
+    new_content_receiver: Receiver<Entry>,
+    /// the new ignore rules when the config is changed
+    ignore_receiver: Receiver<IgnoreRules>,
+    /// the new size limits when the config is changed
+    limits_receiver: Receiver<Limits>,
+    removed_receiver: Receiver<Removed>,
 
-    last_text: Option<Arc<str>>,
-    last_image: Option<ContentImage>,
+    states: HashMap<Selection, SelectionState>,
 }
 
 impl Clipboard {
     pub fn new(
-        content_sender: Sender<Content>,
-        new_content_receiver: Receiver<Content>,
-    ) -> Result<Self> {
-        let x11_clipboard = x11_clipboard::Clipboard::new()
-            .tap_err(|err| error!(?err, "new x11 clipboard failed"))?;
-
-        let png_atom = Self::get_atom(&x11_clipboard.setter.connection, PNG_ATOM)?;
-
-        Ok(Self {
-            x11_clipboard,
-            png_atom,
+        backend: Box<dyn Backend>,
+        limits: Limits,
+        content_sender: Sender<Entry>,
+        new_content_receiver: Receiver<Entry>,
+        ignore_receiver: Receiver<IgnoreRules>,
+        limits_receiver: Receiver<Limits>,
+        removed_receiver: Receiver<Removed>,
+    ) -> Self {
+        Self {
+            backend,
+            limits,
+            ignore: IgnoreRules::default(),
             content_sender,
             new_content_receiver,
-            last_text: None,
-            last_image: None,
-        })
+            ignore_receiver,
+            limits_receiver,
+            removed_receiver,
+            states: HashMap::new(),
+        }
     }
 
     pub fn run(&mut self) -> Result<()> {
+        let changes = self.backend.changes();
+        let save_requests = self.backend.save_requests();
+        let new_content_receiver = self.new_content_receiver.clone();
+        let mut ignore_receiver = self.ignore_receiver.clone();
+        let mut limits_receiver = self.limits_receiver.clone();
+        let mut removed_receiver = self.removed_receiver.clone();
+
         loop {
-            if let Ok(content) = self
-                .new_content_receiver
-                .recv_timeout(Duration::from_millis(50))
-            {
-                match content {
-                    Content::Text(text) => {
-                        self.last_text.replace(text.clone());
-
-                        if self
-                            .x11_clipboard
-                            .store(
-                                self.x11_clipboard.setter.atoms.clipboard,
-                                self.x11_clipboard.setter.atoms.utf8_string,
-                                text.to_string(),
-                            )
-                            .tap_err(|err| error!(?err, %text, "store text to clipboard failed"))
-                            .is_ok()
-                        {
-                            debug!(%text, "set text to clipboard done");
-                        }
-                    }
+            select! {
+                recv(new_content_receiver) -> entry => {
+                    self.restore(entry?);
+                }
 
-                    Content::Image(img) => {
-                        let raw_img = img.raw.clone();
-                        self.last_image.replace(img);
-
-                        if self
-                            .x11_clipboard
-                            .store(
-                                self.x11_clipboard.setter.atoms.clipboard,
-                                self.png_atom,
-                                raw_img.to_vec(),
-                            )
-                            .tap_err(|err| error!(?err, "store image to clipboard failed"))
-                            .is_ok()
-                        {
-                            debug!("set image to clipboard done");
-                        }
-                    }
+                recv(ignore_receiver) -> ignore => match ignore {
+                    Ok(ignore) => self.ignore = ignore,
+                    // the config is not watched anymore
+                    Err(_) => ignore_receiver = crossbeam_channel::never(),
+                },
+
+                recv(limits_receiver) -> limits => match limits {
+                    Ok(limits) => self.limits = limits,
+                    Err(_) => limits_receiver = crossbeam_channel::never(),
+                },
+
+                recv(removed_receiver) -> removed => match removed {
+                    Ok(removed) => self.forget(&removed),
+                    // nothing removes the entries anymore
+                    Err(_) => removed_receiver = crossbeam_channel::never(),
+                },
+
+                recv(changes) -> selection => {
+                    self.capture(selection?)?;
+                }
+
+                recv(save_requests) -> selection => {
+                    self.save(selection?)?;
                 }
             }
+        }
+    }
+
+    /// forget the removed contents in all selections, otherwise copying them again would be
+    /// taken as the same content and ignored
+    fn forget(&mut self, removed: &Removed) {
+        for state in self.states.values_mut() {
+            match removed {
+                Removed::Entry(entry) => state.forget(&entry.content),
+                Removed::All => state.forget_all(),
+            }
+        }
+    }
 
-            if let Ok(Some(text)) = self.get_text() {
-                if let Some(last_text) = self.last_text.as_mut() {
-                    if last_text.as_str() != text {
-                        *last_text = text.into();
+    /// restore the `entry` to the CLIPBOARD, it is sent back to be moved to the history front
+    fn restore(&mut self, mut entry: Entry) {
+        // restored content always goes to the CLIPBOARD selection
+        let state = self.states.entry(Selection::Clipboard).or_default();
 
-                        let _ = self.content_sender.send(Content::Text(last_text.clone()));
-                    }
-                } else {
-                    self.last_text.replace(text.into());
-
-                    self.content_sender
-                        .send(Content::Text(self.last_text.as_ref().unwrap().clone()))
-                        .tap_err(
-                            |err| error!(%err, "send content failed, maybe receiver closed"),
-                        )?;
-                }
+        // we are the owner, the content tells our selection apart
+        state.update(None, Some(&entry.content));
+        state.targets.replace(entry.targets.clone());
 
-                // the latest clipboard content is text, no need to get image
-                continue;
+        let mut targets = entry.targets.to_vec();
+        if let Content::Image(img) = &entry.content {
+            // the apps which only accept the other formats can paste it too
+            targets.extend(img.converted_targets(&targets));
+        }
+
+        let target_count = targets.len();
+        if self
+            .backend
+            .store(Selection::Clipboard, targets.into())
+            .tap_err(|err| error!(?err, "store entry to clipboard failed"))
+            .is_ok()
+        {
+            debug!(targets = target_count, "set entry to clipboard done");
+
+            entry.used_at = now_millis();
+            let _ = self
+                .content_sender
+                .send(entry)
+                .tap_err(|err| error!(%err, "send restored entry failed, maybe receiver closed"));
+        }
+    }
+
+    /// capture the selection content, return true if a new content is captured, the new
+    /// CLIPBOARD content is kept by us, so it is still available after its owner exits. every
+    /// new content is sent once whatever its type is
+    fn capture(&mut self, selection: Selection) -> Result<bool> {
+        let owner = self
+            .backend
+            .owner(selection)
+            .tap_err(|err| warn!(?err, ?selection, "get selection owner failed"))
+            .ok()
+            .flatten();
+
+        // the same owner has the same content, needn't load it again
+        if owner.is_some() && self.states.get(&selection).and_then(|state| state.owner) == owner {
+            debug!(?selection, "selection owner is not changed");
+
+            return Ok(false);
+        }
+
+        let names = match self.backend.load_targets(selection) {
+            Err(err) => {
+                error!(?err, ?selection, "load selection targets failed");
+
+                return Ok(false);
             }
 
-            if let Ok(Some(img)) = self.get_image() {
-                let mut hasher = Md5::new();
-                hasher.update(&img);
-                let sum = *hasher.finalize_fixed().as_mut();
+            Ok(names) => names,
+        };
 
-                if let Some(last_img) = self.last_image.as_mut() {
-                    if sum != last_img.sum {
-                        let image_buf = match ImageBuf::from_data(&img) {
-                            Err(err) => {
-                                error!(%err, "create image buf from raw image data failed");
+        if self.ignore.ignore_targets(&names) {
+            debug!(?selection, "ignore the content by its targets");
+            self.states
+                .entry(selection)
+                .or_default()
+                .update(owner, None);
 
-                                continue;
-                            }
+            return Ok(false);
+        }
 
-                            Ok(image_buf) => image_buf,
-                        };
+        let (content, loaded) = match self.load_content(selection, &names) {
+            None => {
+                self.states
+                    .entry(selection)
+                    .or_default()
+                    .update(owner, None);
 
-                        *last_img = ContentImage {
-                            raw: img.into(),
-                            image_buf,
-                            sum,
-                        };
+                return Ok(false);
+            }
 
-                        self.content_sender
-                            .send(Content::Image(last_img.clone()))
-                            .tap_err(
-                                |err| error!(%err, "send content failed, maybe receiver closed"),
-                            )?;
+            Some(content) => content,
+        };
 
-                        debug!("send image content done");
-                    }
-                } else {
-                    let image_buf = match ImageBuf::from_data(&img) {
-                        Err(err) => {
-                            error!(%err, "create image buf from raw image data failed");
+        if !self
+            .states
+            .entry(selection)
+            .or_default()
+            .update(owner, Some(&content))
+        {
+            debug!(?selection, "selection content is not changed");
 
-                            continue;
-                        }
+            return Ok(false);
+        }
 
-                        Ok(image_buf) => image_buf,
-                    };
+        if self.ignore.ignore_content(&content) {
+            debug!(?selection, "ignore the content by the text patterns");
 
-                    let content_image = ContentImage {
-                        raw: img.into(),
-                        image_buf,
-                        sum,
-                    };
+            return Ok(false);
+        }
 
-                    self.last_image.replace(content_image.clone());
+        let mut targets = self.load_targets(selection, &names, loaded);
 
-                    self.content_sender
-                        .send(Content::Image(content_image))
-                        .tap_err(
-                            |err| error!(%err, "send content failed, maybe receiver closed"),
-                        )?;
+        // make sure the plain text fallback can be pasted
+        if let Content::Html(html) = &content {
+            if !targets
+                .iter()
+                .any(|target| TEXT_TARGETS.contains(&target.name.as_str()))
+            {
+                targets.extend(text_targets(&html.text));
+            }
+        }
 
-                    debug!("send image content done");
-                }
+        let targets: Arc<[Target]> = targets.into();
+        self.states.entry(selection).or_default().targets = Some(targets.clone());
+
+        self.content_sender
+            .send(Entry::new(selection, content, targets))
+            .tap_err(|err| error!(%err, "send content failed, maybe receiver closed"))?;
+
+        debug!(?selection, "send content done");
+
+        // don't take the PRIMARY, it would clear the text selection of the owner
+        if selection == Selection::Clipboard {
+            self.keep(selection);
+        }
+
+        Ok(true)
+    }
+
+    /// save the selection before its owner exits
+    fn save(&mut self, selection: Selection) -> Result<()> {
+        // the new content is kept by the capture
+        let saved = self.capture(selection)? || self.keep(selection);
+
+        self.backend.finish_save(selection, saved);
+
+        Ok(())
+    }
+
+    /// take the selection ownership and serve the last targets, return true if it is kept
+    fn keep(&mut self, selection: Selection) -> bool {
+        let targets = match self
+            .states
+            .get(&selection)
+            .and_then(|state| state.targets.clone())
+        {
+            None => return false,
+            Some(targets) => targets,
+        };
+
+        self.backend
+            .store(selection, targets)
+            .tap_err(|err| error!(?err, ?selection, "keep selection failed"))
+            .is_ok()
+    }
+
+    /// load the content shown in the history and the targets it comes from, None means there
+    /// is no supported content. the image is preferred to the text, the apps offering both
+    /// usually describe the image by the text
+    fn load_content(
+        &mut self,
+        selection: Selection,
+        names: &[String],
+    ) -> Option<(Content, Vec<Target>)> {
+        // the file managers offer the paths as text too, so the files are checked first
+        let files = [files::GNOME_FILES_TARGET, files::URI_LIST_TARGET]
+            .iter()
+            .filter(|files_target| names.iter().any(|name| name == *files_target))
+            .find_map(|files_target| {
+                let target = self.load_target(selection, files_target)?;
+
+                Some((
+                    ContentFiles::parse(&target.name, &target.data.get())?,
+                    target,
+                ))
+            });
+
+        if let Some((files, target)) = files {
+            return Some((Content::Files(files), vec![target]));
+        }
+
+        if let Some((content_image, target)) = self.load_image(selection, names) {
+            return Some((Content::Image(content_image), vec![target]));
+        }
+
+        let text_target = TEXT_TARGETS
+            .iter()
+            .find(|text_target| names.iter().any(|name| name == *text_target))
+            .and_then(|text_target| self.load_target(selection, text_target));
+
+        if names.iter().any(|name| name == HTML_TARGET) {
+            if let Some(target) = self.load_target(selection, HTML_TARGET) {
+                let html = String::from_utf8_lossy(&target.data.get()).into();
+                let text = text_target
+                    .as_ref()
+                    .map(|target| String::from_utf8_lossy(&target.data.get()).into());
+                let content = ContentHtml::new(html, text);
+
+                return Some((
+                    Content::Html(content),
+                    iter::once(target).chain(text_target).collect(),
+                ));
             }
         }
+
+        let target = text_target?;
+        let text = String::from_utf8_lossy(&target.data.get()).into();
+
+        Some((Content::Text(text), vec![target]))
     }
 
-    fn get_text(&mut self) -> Result<Option<String>> {
-        match self.x11_clipboard.load(
-            self.x11_clipboard.setter.atoms.clipboard,
-            self.x11_clipboard.setter.atoms.utf8_string,
-            self.x11_clipboard.setter.atoms.property,
-            Duration::from_millis(50),
-        ) {
-            Err(Error::UnexpectedType(_) | Error::Timeout) => Ok(None),
-            Err(err) => {
-                error!(?err, "get text from clipboard failed");
+    /// load the image of the best offered format which can be decoded, None means no image is
+    /// offered or none of them can be decoded
+    fn load_image(
+        &mut self,
+        selection: Selection,
+        names: &[String],
+    ) -> Option<(ContentImage, Target)> {
+        images::IMAGE_TARGETS
+            .iter()
+            .filter(|(image_target, _)| names.iter().any(|name| name == image_target))
+            .find_map(|(image_target, format)| {
+                let target = self.load_target(selection, image_target)?;
+                let raw = target.data.get();
+                let sum = md5_sum(&raw);
+
+                match ContentImage::decode(raw, *format, sum) {
+                    Err(err) => {
+                        error!(%err, ?format, "decode raw image data failed, try the next target");
+
+                        None
+                    }
+
+                    Ok(content_image) => Some((content_image, target)),
+                }
+            })
+    }
 
-                Err(err.into())
+    /// load the rest targets within the limits, the `loaded` targets are the ones loaded by
+    /// [`Clipboard::load_content`]
+    fn load_targets(
+        &mut self,
+        selection: Selection,
+        names: &[String],
+        loaded: Vec<Target>,
+    ) -> Vec<Target> {
+        let mut total_size = loaded
+            .iter()
+            .map(|target| target.data.get().len())
+            .sum::<usize>();
+        let mut targets = loaded;
+
+        for name in names {
+            if META_TARGETS.contains(&name.as_str())
+                || targets.iter().any(|target| &target.name == name)
+            {
+                continue;
+            }
+
+            let target = match self.load_target(selection, name) {
+                None => continue,
+                Some(target) => target,
+            };
+
+            let size = target.data.get().len();
+            if size > self.limits.max_target_size || total_size + size > self.limits.max_total_size
+            {
+                debug!(name, size, "target exceeds the size limits, drop it");
+
+                continue;
             }
 
-            Ok(text) => Ok((!text.is_empty()).then(|| String::from_utf8_lossy(&text).to_string())),
+            total_size += size;
+            targets.push(target);
         }
+
+        targets
     }
 
-    fn get_image(&mut self) -> Result<Option<Vec<u8>>> {
-        match self.x11_clipboard.load(
-            self.x11_clipboard.setter.atoms.clipboard,
-            self.png_atom,
-            self.x11_clipboard.setter.atoms.property,
-            Duration::from_millis(50),
-        ) {
-            Err(Error::UnexpectedType(_) | Error::Timeout) => Ok(None),
+    fn load_target(&mut self, selection: Selection, name: &str) -> Option<Target> {
+        match self.backend.load(selection, name) {
             Err(err) => {
-                error!(?err, "get image from clipboard failed");
+                error!(?err, ?selection, name, "load selection target failed");
 
-                Err(err.into())
+                None
             }
 
-            Ok(img) => Ok((!img.is_empty()).then(|| img)),
+            Ok(data) => data.map(|data| Target {
+                name: name.to_string(),
+                data: Arc::<[u8]>::from(data).into(),
+            }),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageOutputFormat};
+
+    use super::memory::{MemoryBackend, MemoryHandle};
+    use super::*;
+
+    fn new_clipboard() -> (Clipboard, MemoryHandle, Receiver<Entry>) {
+        new_clipboard_with(MemoryBackend::new(), Limits::default())
+    }
+
+    fn new_clipboard_with_limits(limits: Limits) -> (Clipboard, MemoryHandle, Receiver<Entry>) {
+        new_clipboard_with(MemoryBackend::new(), limits)
+    }
+
+    fn new_clipboard_with(
+        (backend, handle): (MemoryBackend, MemoryHandle),
+        limits: Limits,
+    ) -> (Clipboard, MemoryHandle, Receiver<Entry>) {
+        let (content_sender, content_receiver) = crossbeam_channel::unbounded();
+        let (_new_content_sender, new_content_receiver) = crossbeam_channel::unbounded();
+
+        let clipboard = Clipboard::new(
+            Box::new(backend),
+            limits,
+            content_sender,
+            new_content_receiver,
+            crossbeam_channel::never(),
+            crossbeam_channel::never(),
+            crossbeam_channel::never(),
+        );
+
+        (clipboard, handle, content_receiver)
+    }
+
+    fn png() -> Vec<u8> {
+        encode_image(ImageOutputFormat::Png)
+    }
+
+    fn encode_image(format: ImageOutputFormat) -> Vec<u8> {
+        let mut data = vec![];
+        DynamicImage::new_rgba8(2, 2)
+            .write_to(&mut data, format)
+            .unwrap();
+
+        data
+    }
+
+    fn text_of(entry: Entry) -> String {
+        match entry.content {
+            Content::Text(text) => text.to_string(),
+            content => panic!("unexpected content {:?}", content),
+        }
+    }
+
+    #[test]
+    fn capture_text() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        assert_eq!(entry.selection, Selection::Clipboard);
+        assert_eq!(entry.sum, md5_sum(b"hello"));
+        assert_eq!(entry.size, 5);
+        assert_eq!(entry.use_count, 1);
+        assert_eq!(entry.copied_at, entry.used_at);
+        assert_eq!(text_of(entry), "hello");
+    }
 
-    fn get_atom(connection: &x11_clipboard::xcb::Connection, name: &str) -> Result<Atom> {
-        let req = connection.send_request(&InternAtom {
-            only_if_exists: false,
-            name: name.as_bytes(),
+    #[test]
+    fn capture_selection_separately() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.copy(Selection::Primary, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Primary).unwrap();
+
+        let clipboard_entry = content_receiver.try_recv().unwrap();
+        let primary_entry = content_receiver.try_recv().unwrap();
+        assert_eq!(clipboard_entry.selection, Selection::Clipboard);
+        assert_eq!(primary_entry.selection, Selection::Primary);
+        // the same content copied twice is two entries
+        assert_ne!(clipboard_entry.id, primary_entry.id);
+        assert!(!clipboard_entry.same(&primary_entry));
+    }
+
+    /// the kinds of the captured contents
+    fn captured_kinds(content_receiver: &Receiver<Entry>) -> Vec<&'static str> {
+        content_receiver
+            .try_iter()
+            .map(|entry| match entry.content {
+                Content::Text(_) => "text",
+                Content::Html(_) => "html",
+                Content::Files(_) => "files",
+                Content::Image(_) => "image",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn capture_every_change() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+        let png = png();
+
+        // the PRIMARY is never kept, so its owner is always the app which copied it
+        handle.copy(Selection::Primary, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Primary).unwrap();
+        handle.copy(Selection::Primary, &[("image/png", &png)]);
+        clipboard.capture(Selection::Primary).unwrap();
+        // the text before the image is not the current content anymore
+        handle.copy(Selection::Primary, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Primary).unwrap();
+        assert_eq!(captured_kinds(&content_receiver), ["text", "image", "text"]);
+
+        // the same owner is not a change, however often it is reported
+        for _ in 0..2 {
+            handle.notify(Selection::Primary);
+            clipboard.capture(Selection::Primary).unwrap();
+        }
+        assert!(content_receiver.try_recv().is_err());
+
+        // copying the same content again is a change, the history moves it to the front
+        handle.copy(Selection::Primary, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Primary).unwrap();
+        assert_eq!(captured_kinds(&content_receiver), ["text"]);
+    }
+
+    #[test]
+    fn compare_contents_of_unknown_owners() {
+        let (mut clipboard, handle, content_receiver) =
+            new_clipboard_with(MemoryBackend::without_owners(), Limits::default());
+        let png = png();
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        // polled again, or copied again, they can't be told apart
+        handle.notify(Selection::Clipboard);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert_eq!(captured_kinds(&content_receiver), ["text"]);
+
+        handle.copy(Selection::Clipboard, &[("image/png", &png)]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert_eq!(captured_kinds(&content_receiver), ["image", "text"]);
+
+        // the kept selection is ours, it is not a new content
+        handle.notify(Selection::Clipboard);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert!(content_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn prefer_image_to_text() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(
+            Selection::Clipboard,
+            &[(TEXT_TARGET, b"a picture"), ("image/png", &png())],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        assert!(matches!(entry.content, Content::Image(_)));
+        // the text is still pasted
+        assert!(entry
+            .targets
+            .iter()
+            .any(|target| target.name == TEXT_TARGET));
+    }
+
+    #[test]
+    fn capture_removed_again() {
+        let (mut clipboard, handle, content_receiver) =
+            new_clipboard_with(MemoryBackend::without_owners(), Limits::default());
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"secret")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        let entry = content_receiver.try_recv().unwrap();
+
+        clipboard.forget(&Removed::Entry(entry));
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"secret")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert_eq!(text_of(content_receiver.try_recv().unwrap()), "secret");
+
+        clipboard.forget(&Removed::All);
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"secret")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert_eq!(text_of(content_receiver.try_recv().unwrap()), "secret");
+    }
+
+    #[test]
+    fn keep_pinned_entries() {
+        let mut contents = ["a", "b", "c", "d"]
+            .into_iter()
+            .map(|text| Entry::from_content(Selection::Clipboard, text.to_string().into()))
+            .collect::<Vector<_>>();
+        contents[3].pinned = true;
+
+        truncate_history(&mut contents, 2);
+        assert_eq!(
+            contents.into_iter().map(text_of).collect::<Vec<_>>(),
+            ["a", "b", "d"]
+        );
+
+        // the im truncate panics on a shorter vector
+        let mut contents = Vector::new();
+        truncate_history(&mut contents, 2);
+    }
+
+    #[test]
+    fn promote_copied_again() {
+        let entry = |text: &str| Entry::from_content(Selection::Clipboard, text.to_string().into());
+        let mut contents = Vector::new();
+
+        let first = entry("a");
+        push_history(&mut contents, first.clone(), 10);
+        push_history(&mut contents, entry("b"), 10);
+        push_history(&mut contents, entry("a"), 10);
+
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[0].id, first.id);
+        assert_eq!(contents[0].use_count, 2);
+        assert_eq!(text_of(contents[1].clone()), "b");
+
+        // the html of the same text is another content
+        let html = Content::Html(ContentHtml::new("a".into(), None));
+        push_history(
+            &mut contents,
+            Entry::from_content(Selection::Clipboard, html),
+            10,
+        );
+        assert_eq!(contents.len(), 3);
+    }
+
+    #[test]
+    fn capture_image() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+        let png = png();
+
+        handle.copy(Selection::Clipboard, &[("image/png", &png)]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.notify(Selection::Clipboard);
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        match content_receiver.try_recv().unwrap().content {
+            Content::Image(img) => assert_eq!(img.raw.as_ref(), png.as_slice()),
+            content => panic!("unexpected content {:?}", content),
+        }
+        assert!(content_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn capture_best_image_target() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+        let bmp = encode_image(ImageOutputFormat::Bmp);
+        let jpeg = encode_image(ImageOutputFormat::Jpeg(90));
+
+        handle.copy(
+            Selection::Clipboard,
+            &[("image/jpeg", &jpeg), ("image/bmp", &bmp)],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        match &entry.content {
+            Content::Image(img) => assert_eq!(img.format, ImageFormat::Bmp),
+            content => panic!("unexpected content {:?}", content),
+        }
+
+        // png is not offered by the owner, it is converted when pasting
+        clipboard.restore(entry);
+        let png = handle.paste(Selection::Clipboard, "image/png").unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn capture_decodable_image_target() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+        let bmp = encode_image(ImageOutputFormat::Bmp);
+
+        handle.copy(
+            Selection::Clipboard,
+            &[("image/png", b"broken"), ("image/bmp", &bmp)],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        match content_receiver.try_recv().unwrap().content {
+            Content::Image(img) => assert_eq!(img.format, ImageFormat::Bmp),
+            content => panic!("unexpected content {:?}", content),
+        }
+    }
+
+    #[test]
+    fn capture_all_targets() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(
+            Selection::Clipboard,
+            &[
+                ("TARGETS", b"ignored"),
+                ("application/x-app-data", b"data"),
+                (TEXT_TARGET, b"hello"),
+            ],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        let names = entry
+            .targets
+            .iter()
+            .map(|target| target.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [TEXT_TARGET, "application/x-app-data"]);
+    }
+
+    #[test]
+    fn drop_too_large_targets() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard_with_limits(Limits {
+            max_target_size: 8,
+            max_total_size: 12,
         });
 
-        Ok(connection
-            .wait_for_reply(req)
-            .tap_err(|err| error!(?err, name, "get atom failed"))?
-            .atom())
+        handle.copy(
+            Selection::Clipboard,
+            &[
+                (TEXT_TARGET, b"hello"),
+                ("application/x-app-data", b"too large data"),
+                ("text/x-a", b"1234"),
+                ("text/x-b", b"1234"),
+            ],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        let names = entry
+            .targets
+            .iter()
+            .map(|target| target.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [TEXT_TARGET, "text/x-a"]);
+    }
+
+    #[test]
+    fn restore_all_targets() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(
+            Selection::Primary,
+            &[(TEXT_TARGET, b"hello"), ("application/x-app-data", b"data")],
+        );
+        clipboard.capture(Selection::Primary).unwrap();
+        clipboard.restore(content_receiver.try_recv().unwrap());
+
+        assert_eq!(
+            handle
+                .paste(Selection::Clipboard, "application/x-app-data")
+                .unwrap()
+                .as_ref(),
+            b"data"
+        );
+    }
+
+    #[test]
+    fn capture_html() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(
+            Selection::Clipboard,
+            &[(HTML_TARGET, b"<p>hello <b>world</b></p><p>a &amp; b</p>")],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        match &entry.content {
+            Content::Html(html) => assert_eq!(html.text.as_ref(), "hello world\na & b"),
+            content => panic!("unexpected content {:?}", content),
+        }
+
+        // the stripped text is offered as the fallback
+        assert!(entry
+            .targets
+            .iter()
+            .any(|target| target.name == TEXT_TARGET));
+
+        let plain = entry.to_plain_text().unwrap();
+        assert!(plain
+            .targets
+            .iter()
+            .all(|target| target.name != HTML_TARGET));
+    }
+
+    #[test]
+    fn capture_files() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        handle.copy(
+            Selection::Clipboard,
+            &[
+                (TEXT_TARGET, b"/tmp/a b.txt"),
+                (files::URI_LIST_TARGET, b"file:///tmp/a%20b.txt\r\n"),
+                (files::GNOME_FILES_TARGET, b"cut\nfile:///tmp/a%20b.txt"),
+            ],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        let entry = content_receiver.try_recv().unwrap();
+        match &entry.content {
+            Content::Files(files) => {
+                assert_eq!(files.operation, FileOperation::Cut);
+                assert_eq!(files.files.len(), 1);
+                assert_eq!(files.files[0].name.as_ref(), "a b.txt");
+            }
+
+            content => panic!("unexpected content {:?}", content),
+        }
+
+        // the file manager needs the exact targets to paste the files
+        clipboard.restore(entry);
+        assert_eq!(
+            handle
+                .paste(Selection::Clipboard, files::GNOME_FILES_TARGET)
+                .unwrap()
+                .as_ref(),
+            b"cut\nfile:///tmp/a%20b.txt"
+        );
+    }
+
+    #[test]
+    fn keep_clipboard_after_owner_exits() {
+        let (mut clipboard, handle, _content_receiver) = new_clipboard();
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.close(Selection::Clipboard);
+
+        assert_eq!(
+            handle
+                .paste(Selection::Clipboard, TEXT_TARGET)
+                .unwrap()
+                .as_ref(),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn save_nothing_after_ignored_content() {
+        let (mut clipboard, handle, _content_receiver) = new_clipboard();
+        clipboard.ignore = IgnoreRules {
+            targets: vec![],
+            text_patterns: vec![Regex::new(r"^\d{6}$").unwrap()],
+        };
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"hello")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"123456")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+
+        clipboard.save(Selection::Clipboard).unwrap();
+        handle.close(Selection::Clipboard);
+
+        assert!(handle.paste(Selection::Clipboard, TEXT_TARGET).is_none());
+    }
+
+    #[test]
+    fn restore_text() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+
+        clipboard.restore(Entry::from_content(
+            Selection::Clipboard,
+            Content::Text("hello".into()),
+        ));
+
+        assert_eq!(
+            handle
+                .paste(Selection::Clipboard, TEXT_TARGET)
+                .unwrap()
+                .as_ref(),
+            b"hello"
+        );
+
+        // the restored entry is sent back once, it is not captured as a new content
+        assert_eq!(text_of(content_receiver.try_recv().unwrap()), "hello");
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert!(content_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn ignore_content() {
+        let (mut clipboard, handle, content_receiver) = new_clipboard();
+        clipboard.ignore = IgnoreRules {
+            targets: vec!["x-kde-passwordManagerHint".to_string()],
+            text_patterns: vec![Regex::new(r"^\d{6}$").unwrap()],
+        };
+
+        handle.copy(
+            Selection::Clipboard,
+            &[
+                (TEXT_TARGET, b"password"),
+                ("x-kde-passwordManagerHint", b"secret"),
+            ],
+        );
+        clipboard.capture(Selection::Clipboard).unwrap();
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"123456")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert!(content_receiver.try_recv().is_err());
+
+        handle.copy(Selection::Clipboard, &[(TEXT_TARGET, b"1234567")]);
+        clipboard.capture(Selection::Clipboard).unwrap();
+        assert_eq!(text_of(content_receiver.try_recv().unwrap()), "1234567");
     }
 }
@@ -0,0 +1,538 @@
+//! the wayland backend, it uses the ext-data-control or wlr-data-control protocol, which allow
+//! a privileged client to manage the selections without a focused surface
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::AsFd;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{Receiver, Sender};
+use rustix::event::{self, PollFd, PollFlags};
+use rustix::io::Errno;
+use tap::TapFallible;
+use tracing::{debug, error, warn};
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1, ext_data_control_manager_v1, ext_data_control_offer_v1,
+    ext_data_control_source_v1,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1, zwlr_data_control_manager_v1, zwlr_data_control_offer_v1,
+    zwlr_data_control_source_v1,
+};
+
+use super::backend::{Backend, Owner};
+use super::{Selection, Target};
+
+/// the size of the chunks read from the pipe
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// wrap the same requests of the ext and wlr protocols
+macro_rules! data_control_enum {
+    ($name:ident, $ext:ty, $wlr:ty) => {
+        #[derive(Debug, Clone)]
+        enum $name {
+            Ext($ext),
+            Wlr($wlr),
+        }
+    };
+}
+
+data_control_enum!(
+    Manager,
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1
+);
+data_control_enum!(
+    Device,
+    ext_data_control_device_v1::ExtDataControlDeviceV1,
+    zwlr_data_control_device_v1::ZwlrDataControlDeviceV1
+);
+data_control_enum!(
+    Offer,
+    ext_data_control_offer_v1::ExtDataControlOfferV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1
+);
+data_control_enum!(
+    Source,
+    ext_data_control_source_v1::ExtDataControlSourceV1,
+    zwlr_data_control_source_v1::ZwlrDataControlSourceV1
+);
+
+impl Offer {
+    fn id(&self) -> ObjectId {
+        match self {
+            Offer::Ext(offer) => offer.id(),
+            Offer::Wlr(offer) => offer.id(),
+        }
+    }
+
+    fn receive(&self, mime_type: String, fd: std::os::fd::BorrowedFd) {
+        match self {
+            Offer::Ext(offer) => offer.receive(mime_type, fd),
+            Offer::Wlr(offer) => offer.receive(mime_type, fd),
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            Offer::Ext(offer) => offer.destroy(),
+            Offer::Wlr(offer) => offer.destroy(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    /// the mime types of the offers which are not the selection yet
+    offers: HashMap<ObjectId, Vec<String>>,
+    /// the current offer of the selections
+    selections: HashMap<Selection, (Offer, Vec<String>)>,
+    /// the serials of the current offers, every offer has a new one
+    owners: HashMap<Selection, u64>,
+    last_serial: u64,
+    /// the selections set by ourselves, their next changes should be ignored
+    self_set: HashSet<Selection>,
+}
+
+/// the state of the event queue
+struct State {
+    shared: Arc<Mutex<Shared>>,
+    selections: Vec<Selection>,
+    change_sender: Sender<Selection>,
+}
+
+impl State {
+    fn offer_mime_type(&mut self, offer: ObjectId, mime_type: String) {
+        if let Some(mime_types) = self.shared.lock().unwrap().offers.get_mut(&offer) {
+            mime_types.push(mime_type);
+        }
+    }
+
+    fn set_selection(&mut self, selection: Selection, offer: Option<Offer>) {
+        let mut shared = self.shared.lock().unwrap();
+
+        let old = match offer {
+            None => {
+                shared.owners.remove(&selection);
+
+                shared.selections.remove(&selection)
+            }
+
+            Some(offer) => {
+                let mime_types = shared.offers.remove(&offer.id()).unwrap_or_default();
+                shared.last_serial += 1;
+                let serial = shared.last_serial;
+                shared.owners.insert(selection, serial);
+
+                shared.selections.insert(selection, (offer, mime_types))
+            }
+        };
+
+        if let Some((old_offer, _)) = old {
+            old_offer.destroy();
+        }
+
+        if shared.self_set.remove(&selection) || !self.selections.contains(&selection) {
+            return;
+        }
+
+        if shared.selections.contains_key(&selection) {
+            debug!(?selection, "selection changed");
+
+            let _ = self.change_sender.send(selection);
+        }
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_seat::WlSeat,
+        _event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// the ext and wlr protocols have the same events, implement the Dispatch for both of them
+macro_rules! impl_data_control_dispatch {
+    (
+        $variant:ident,
+        $manager:ident::$manager_ty:ident,
+        $device:ident::$device_ty:ident,
+        $offer:ident::$offer_ty:ident,
+        $source:ident::$source_ty:ident
+    ) => {
+        impl Dispatch<$manager::$manager_ty, ()> for State {
+            fn event(
+                _state: &mut Self,
+                _proxy: &$manager::$manager_ty,
+                _event: $manager::Event,
+                _data: &(),
+                _conn: &Connection,
+                _qhandle: &QueueHandle<Self>,
+            ) {
+            }
+        }
+
+        impl Dispatch<$device::$device_ty, ()> for State {
+            fn event(
+                state: &mut Self,
+                _proxy: &$device::$device_ty,
+                event: $device::Event,
+                _data: &(),
+                _conn: &Connection,
+                _qhandle: &QueueHandle<Self>,
+            ) {
+                match event {
+                    $device::Event::DataOffer { id } => {
+                        state.shared.lock().unwrap().offers.insert(id.id(), vec![]);
+                    }
+
+                    $device::Event::Selection { id } => {
+                        state.set_selection(Selection::Clipboard, id.map(Offer::$variant));
+                    }
+
+                    $device::Event::PrimarySelection { id } => {
+                        state.set_selection(Selection::Primary, id.map(Offer::$variant));
+                    }
+
+                    $device::Event::Finished => {
+                        warn!("data control device is finished, selections won't be updated");
+                    }
+
+                    _ => {}
+                }
+            }
+
+            event_created_child!(State, $device::$device_ty, [
+                $device::EVT_DATA_OFFER_OPCODE => ($offer::$offer_ty, ()),
+            ]);
+        }
+
+        impl Dispatch<$offer::$offer_ty, ()> for State {
+            fn event(
+                state: &mut Self,
+                proxy: &$offer::$offer_ty,
+                event: $offer::Event,
+                _data: &(),
+                _conn: &Connection,
+                _qhandle: &QueueHandle<Self>,
+            ) {
+                if let $offer::Event::Offer { mime_type } = event {
+                    state.offer_mime_type(proxy.id(), mime_type);
+                }
+            }
+        }
+
+        impl Dispatch<$source::$source_ty, Arc<[Target]>> for State {
+            fn event(
+                _state: &mut Self,
+                proxy: &$source::$source_ty,
+                event: $source::Event,
+                targets: &Arc<[Target]>,
+                _conn: &Connection,
+                _qhandle: &QueueHandle<Self>,
+            ) {
+                match event {
+                    $source::Event::Send { mime_type, fd } => {
+                        let data = match targets.iter().find(|target| target.name == mime_type) {
+                            None => {
+                                debug!(mime_type, "request a mime type we don't offer");
+
+                                return;
+                            }
+
+                            Some(target) => target.data.clone(),
+                        };
+
+                        // don't block the event queue by the slow reader
+                        thread::spawn(move || {
+                            if let Err(err) = File::from(fd).write_all(&data.get()) {
+                                debug!(%err, mime_type, "send selection content failed");
+                            }
+                        });
+                    }
+
+                    $source::Event::Cancelled => {
+                        proxy.destroy();
+                    }
+
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+impl_data_control_dispatch!(
+    Ext,
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    ext_data_control_device_v1::ExtDataControlDeviceV1,
+    ext_data_control_offer_v1::ExtDataControlOfferV1,
+    ext_data_control_source_v1::ExtDataControlSourceV1
+);
+impl_data_control_dispatch!(
+    Wlr,
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::ZwlrDataControlSourceV1
+);
+
+pub struct WaylandBackend {
+    connection: Connection,
+    queue_handle: QueueHandle<State>,
+    manager: Manager,
+    device: Device,
+    shared: Arc<Mutex<Shared>>,
+    changes: Receiver<Selection>,
+    /// how long to wait the selection owner to write the next chunk of the content
+    load_timeout: Duration,
+}
+
+impl WaylandBackend {
+    pub fn new(selections: &[Selection], load_timeout: Duration) -> Result<Self> {
+        let connection = Connection::connect_to_env()
+            .tap_err(|err| error!(%err, "connect wayland compositor failed"))?;
+
+        let (globals, mut queue) = registry_queue_init::<State>(&connection)
+            .tap_err(|err| error!(%err, "init wayland registry failed"))?;
+        let queue_handle = queue.handle();
+
+        let seat: wl_seat::WlSeat = globals
+            .bind(&queue_handle, 1..=1, ())
+            .tap_err(|err| error!(%err, "bind wayland seat failed"))?;
+
+        let manager = match globals.bind(&queue_handle, 1..=1, ()) {
+            Ok(manager) => Manager::Ext(manager),
+            Err(_) => Manager::Wlr(globals.bind(&queue_handle, 1..=2, ()).map_err(|err| {
+                anyhow!("compositor doesn't support data control protocol: {}", err)
+            })?),
+        };
+
+        if selections.contains(&Selection::Secondary) {
+            warn!("wayland doesn't have the secondary selection");
+        }
+        if matches!(&manager, Manager::Wlr(manager) if manager.version() < 2)
+            && selections.contains(&Selection::Primary)
+        {
+            warn!("compositor doesn't support the primary selection");
+        }
+
+        let device = match &manager {
+            Manager::Ext(manager) => Device::Ext(manager.get_data_device(&seat, &queue_handle, ())),
+            Manager::Wlr(manager) => Device::Wlr(manager.get_data_device(&seat, &queue_handle, ())),
+        };
+
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (change_sender, changes) = crossbeam_channel::unbounded();
+
+        let mut state = State {
+            shared: shared.clone(),
+            selections: selections.to_vec(),
+            change_sender,
+        };
+
+        thread::spawn(move || loop {
+            if let Err(err) = queue.blocking_dispatch(&mut state) {
+                error!(%err, "dispatch wayland events failed");
+
+                return;
+            }
+        });
+
+        Ok(Self {
+            connection,
+            queue_handle,
+            manager,
+            device,
+            shared,
+            changes,
+            load_timeout,
+        })
+    }
+}
+
+impl Backend for WaylandBackend {
+    fn changes(&self) -> Receiver<Selection> {
+        self.changes.clone()
+    }
+
+    /// every offer is a new content, the compositor doesn't tell the time
+    fn owner(&mut self, selection: Selection) -> Result<Option<Owner>> {
+        Ok(self
+            .shared
+            .lock()
+            .unwrap()
+            .owners
+            .get(&selection)
+            .map(|serial| Owner {
+                id: *serial,
+                timestamp: 0,
+            }))
+    }
+
+    fn load_targets(&mut self, selection: Selection) -> Result<Vec<String>> {
+        Ok(self
+            .shared
+            .lock()
+            .unwrap()
+            .selections
+            .get(&selection)
+            .map(|(_, mime_types)| mime_types.clone())
+            .unwrap_or_default())
+    }
+
+    fn load(&mut self, selection: Selection, target: &str) -> Result<Option<Vec<u8>>> {
+        let offer = match self.shared.lock().unwrap().selections.get(&selection) {
+            Some((offer, mime_types)) if mime_types.iter().any(|mime_type| mime_type == target) => {
+                offer.clone()
+            }
+
+            _ => return Ok(None),
+        };
+
+        let (reader, writer) = rustix::pipe::pipe()?;
+
+        offer.receive(target.to_string(), writer.as_fd());
+        self.connection.flush()?;
+
+        // close our write end, then the reading ends when the owner closes its write end
+        drop(writer);
+
+        // the reader is dropped on timeout, so the owner gets EPIPE instead of blocking forever
+        let mut reader = File::from(reader);
+        let mut content = vec![];
+        let mut chunk = vec![0; CHUNK_SIZE];
+
+        loop {
+            let mut fds = [PollFd::new(&reader, PollFlags::IN)];
+            match event::poll(&mut fds, self.load_timeout.as_millis() as i32) {
+                Err(Errno::INTR) => continue,
+                Err(err) => return Err(err.into()),
+
+                Ok(0) => {
+                    debug!(?selection, target, "wait selection owner timeout");
+
+                    return Ok(None);
+                }
+
+                // readable or hung up, the read ends with 0 after the owner closes
+                Ok(_) => match reader.read(&mut chunk) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err.into()),
+                    Ok(0) => break,
+                    Ok(size) => content.extend_from_slice(&chunk[..size]),
+                },
+            }
+        }
+
+        Ok(Some(content).filter(|content| !content.is_empty()))
+    }
+
+    fn store(&mut self, selection: Selection, targets: Arc<[Target]>) -> Result<()> {
+        let source = match &self.manager {
+            Manager::Ext(manager) => {
+                Source::Ext(manager.create_data_source(&self.queue_handle, targets.clone()))
+            }
+            Manager::Wlr(manager) => {
+                Source::Wlr(manager.create_data_source(&self.queue_handle, targets.clone()))
+            }
+        };
+
+        for target in targets.iter() {
+            match &source {
+                Source::Ext(source) => source.offer(target.name.clone()),
+                Source::Wlr(source) => source.offer(target.name.clone()),
+            }
+        }
+
+        self.shared.lock().unwrap().self_set.insert(selection);
+
+        match (&self.device, &source, selection) {
+            (Device::Ext(device), Source::Ext(source), Selection::Clipboard) => {
+                device.set_selection(Some(source))
+            }
+            (Device::Ext(device), Source::Ext(source), Selection::Primary) => {
+                device.set_primary_selection(Some(source))
+            }
+            (Device::Wlr(device), Source::Wlr(source), Selection::Clipboard) => {
+                device.set_selection(Some(source))
+            }
+            (Device::Wlr(device), Source::Wlr(source), Selection::Primary)
+                if device.version() >= 2 =>
+            {
+                device.set_primary_selection(Some(source))
+            }
+
+            _ => {
+                self.shared.lock().unwrap().self_set.remove(&selection);
+
+                return Err(anyhow!("can't set the {:?} selection", selection));
+            }
+        }
+
+        self.connection
+            .flush()
+            .tap_err(|err| error!(%err, "flush wayland connection failed"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::Entry;
+
+    /// run it in a compositor which supports the data control protocol, for example
+    /// `WLR_BACKENDS=headless sway`, then `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn store_and_load() {
+        let mut backend =
+            WaylandBackend::new(&[Selection::Clipboard], Duration::from_secs(1)).unwrap();
+
+        let entry = Entry::from_content(Selection::Clipboard, "hello".to_string().into());
+        backend.store(Selection::Clipboard, entry.targets).unwrap();
+
+        // wait the compositor announces our selection
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(backend
+            .load_targets(Selection::Clipboard)
+            .unwrap()
+            .iter()
+            .any(|target| target == "text/plain;charset=utf-8"));
+        assert_eq!(
+            backend
+                .load(Selection::Clipboard, "text/plain;charset=utf-8")
+                .unwrap()
+                .unwrap(),
+            b"hello"
+        );
+    }
+}
@@ -0,0 +1,963 @@
+//! the X11 backend, it talks to the X server by xcb directly, because we need to know the
+//! targets the selection owner offers, which the x11_clipboard crate can't provide
+
+use std::collections::HashMap;
+use std::iter;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{Receiver, Sender};
+use tap::TapFallible;
+use tracing::{debug, error, info, warn};
+use xcb::x::{self, Atom, Window};
+use xcb::{Connection, Xid};
+
+use super::backend::{Backend, Owner};
+use super::{xfixes, Selection, Target, TargetData};
+use crate::config::{Hotkey, Modifier};
+
+/// the property of our window to receive the converted selection content
+const PROPERTY_NAME: &str = "HISTORY_CLIPBOARD_OUT";
+/// the INCR transfer is dropped when the requestor doesn't take a chunk in time
+const INCR_TIMEOUT: Duration = Duration::from_secs(5);
+/// the CapsLock and NumLock combinations, they don't change the hotkey
+const LOCK_MODIFIERS: [x::ModMask; 4] = [
+    x::ModMask::empty(),
+    x::ModMask::LOCK,
+    x::ModMask::N2,
+    x::ModMask::LOCK.union(x::ModMask::N2),
+];
+
+#[derive(Debug, Copy, Clone)]
+struct Atoms {
+    clipboard: Atom,
+    targets: Atom,
+    incr: Atom,
+    property: Atom,
+    clipboard_manager: Atom,
+    save_targets: Atom,
+}
+
+/// the selection content we own, selection -> [(target, data)]
+type Owned = Arc<Mutex<HashMap<Atom, Vec<(Atom, TargetData)>>>>;
+
+/// the content sent by INCR in chunks, (requestor, property) -> transfer
+type Transfers = HashMap<(Window, Atom), Transfer>;
+
+#[derive(Debug)]
+struct Transfer {
+    target: Atom,
+    data: Arc<[u8]>,
+    /// the size already sent
+    offset: usize,
+    /// when the last chunk is sent
+    updated: Instant,
+}
+
+/// a SelectionRequest waits for the response
+#[derive(Debug, Copy, Clone)]
+struct Request {
+    time: x::Timestamp,
+    requestor: Window,
+    selection: Atom,
+    target: Atom,
+    property: Atom,
+}
+
+impl From<&x::SelectionRequestEvent> for Request {
+    fn from(event: &x::SelectionRequestEvent) -> Self {
+        Self {
+            time: event.time(),
+            requestor: event.requestor(),
+            selection: event.selection(),
+            target: event.target(),
+            // the obsolete clients may use None as the property
+            property: if event.property() == x::ATOM_NONE {
+                event.target()
+            } else {
+                event.property()
+            },
+        }
+    }
+}
+
+/// the state of the CLIPBOARD_MANAGER selection
+#[derive(Debug)]
+struct Manager {
+    /// the SAVE_TARGETS requests wait the CLIPBOARD is saved
+    pending: Mutex<Vec<Request>>,
+    save_sender: Sender<Selection>,
+}
+
+pub struct X11Backend {
+    connection: Arc<Connection>,
+    window: Window,
+    atoms: Atoms,
+    atom_cache: HashMap<String, Atom>,
+    atom_names: HashMap<Atom, String>,
+    owned: Owned,
+    manager: Arc<Manager>,
+    /// how long to wait the selection owner to respond a conversion, the timer is reset when a
+    /// chunk of an INCR transfer is received
+    load_timeout: Duration,
+
+    /// the SelectionNotify and PropertyNotify events of our window
+    notify_receiver: Receiver<xcb::Event>,
+    changes: Receiver<Selection>,
+    save_requests: Receiver<Selection>,
+    hotkey_presses: Receiver<(f64, f64)>,
+}
+
+/// the grabbed hotkey
+struct HotkeyGrab {
+    keycodes: Vec<x::Keycode>,
+    modifiers: x::ModMask,
+    press_sender: Sender<(f64, f64)>,
+}
+
+impl X11Backend {
+    /// the selections are polled every `poll_interval` when the XFixes is not supported, the
+    /// `hotkey` is grabbed on the root window
+    pub fn new(
+        selections: &[Selection],
+        poll_interval: Duration,
+        load_timeout: Duration,
+        hotkey: Option<&Hotkey>,
+    ) -> Result<Self> {
+        let (connection, screen_num) =
+            Connection::connect(None).tap_err(|err| error!(?err, "connect x11 server failed"))?;
+        let connection = Arc::new(connection);
+
+        let screen = connection
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .ok_or_else(|| anyhow!("screen {} not found", screen_num))?;
+
+        let window = connection.generate_id();
+        connection
+            .send_and_check_request(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8,
+                wid: window,
+                parent: screen.root(),
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+                border_width: 0,
+                class: x::WindowClass::InputOutput,
+                visual: screen.root_visual(),
+                value_list: &[x::Cw::EventMask(x::EventMask::PROPERTY_CHANGE)],
+            })
+            .tap_err(|err| error!(?err, "create x11 window failed"))?;
+
+        let atoms = Atoms {
+            clipboard: get_atom(&connection, "CLIPBOARD")?,
+            targets: get_atom(&connection, "TARGETS")?,
+            incr: get_atom(&connection, "INCR")?,
+            property: get_atom(&connection, PROPERTY_NAME)?,
+            clipboard_manager: get_atom(&connection, "CLIPBOARD_MANAGER")?,
+            save_targets: get_atom(&connection, "SAVE_TARGETS")?,
+        };
+
+        let (press_sender, hotkey_presses) = crossbeam_channel::unbounded();
+        let hotkey = hotkey.and_then(|hotkey| {
+            grab_hotkey(&connection, screen.root(), hotkey, press_sender)
+                .tap_err(|err| warn!(%err, ?hotkey, "grab hotkey failed"))
+                .ok()
+        });
+
+        let owned = Owned::default();
+        let (notify_sender, notify_receiver) = crossbeam_channel::unbounded();
+        let (save_sender, save_requests) = crossbeam_channel::unbounded();
+        let manager = Arc::new(Manager {
+            pending: Mutex::default(),
+            save_sender,
+        });
+
+        {
+            let connection = connection.clone();
+            let owned = owned.clone();
+            let manager = manager.clone();
+
+            thread::spawn(move || {
+                handle_events(
+                    &connection,
+                    window,
+                    atoms,
+                    &owned,
+                    &manager,
+                    hotkey.as_ref(),
+                    notify_sender,
+                )
+            });
+        }
+
+        if let Err(err) = become_clipboard_manager(&connection, window, atoms) {
+            warn!(?err, "become the clipboard manager failed");
+        }
+
+        let selection_atoms = selections
+            .iter()
+            .map(|&selection| (selection, selection_atom(&atoms, selection)))
+            .collect::<Vec<_>>();
+
+        let changes = match xfixes::watch(&selection_atoms, window)? {
+            Some(changes) => changes,
+            None => {
+                info!("xfixes is not available, fallback to poll the selections");
+
+                poll_selections(selections.to_vec(), poll_interval)
+            }
+        };
+
+        Ok(Self {
+            connection,
+            window,
+            atoms,
+            atom_cache: HashMap::new(),
+            atom_names: HashMap::new(),
+            owned,
+            manager,
+            load_timeout,
+            notify_receiver,
+            changes,
+            save_requests,
+            hotkey_presses,
+        })
+    }
+
+    fn atom(&mut self, name: &str) -> Result<Atom> {
+        if let Some(atom) = self.atom_cache.get(name) {
+            return Ok(*atom);
+        }
+
+        let atom = get_atom(&self.connection, name)?;
+        self.atom_cache.insert(name.to_string(), atom);
+        self.atom_names.insert(atom, name.to_string());
+
+        Ok(atom)
+    }
+
+    fn atom_name(&mut self, atom: Atom) -> Result<String> {
+        if let Some(name) = self.atom_names.get(&atom) {
+            return Ok(name.clone());
+        }
+
+        let cookie = self.connection.send_request(&x::GetAtomName { atom });
+        let name = self
+            .connection
+            .wait_for_reply(cookie)
+            .tap_err(|err| error!(?err, ?atom, "get atom name failed"))?
+            .name()
+            .to_utf8()
+            .to_string();
+
+        self.atom_cache.insert(name.clone(), atom);
+        self.atom_names.insert(atom, name.clone());
+
+        Ok(name)
+    }
+
+    /// ask the selection owner to convert the content to `target`, return the property replies
+    /// holding the content, there are multiple replies when the owner transfers the content by
+    /// INCR. None means the owner can't convert it or doesn't respond
+    fn convert(
+        &mut self,
+        selection: Atom,
+        target: Atom,
+    ) -> Result<Option<Vec<x::GetPropertyReply>>> {
+        // drop the stale notifies of the previous conversion
+        while self.notify_receiver.try_recv().is_ok() {}
+
+        self.connection
+            .send_and_check_request(&x::ConvertSelection {
+                requestor: self.window,
+                selection,
+                target,
+                property: self.atoms.property,
+                time: x::CURRENT_TIME,
+            })
+            .tap_err(|err| error!(?err, "convert selection failed"))?;
+
+        // Some when the owner transfers the content by INCR
+        let mut incr_replies: Option<Vec<x::GetPropertyReply>> = None;
+
+        loop {
+            let event = match self.notify_receiver.recv_timeout(self.load_timeout) {
+                Err(_) => {
+                    debug!(?selection, ?target, "wait selection owner timeout");
+
+                    return Ok(None);
+                }
+
+                Ok(event) => event,
+            };
+
+            match (event, incr_replies.as_mut()) {
+                (xcb::Event::X(x::Event::SelectionNotify(event)), None) => {
+                    if event.selection() != selection || event.target() != target {
+                        continue;
+                    }
+
+                    // the owner can't convert the content to the target
+                    if event.property() == x::ATOM_NONE {
+                        return Ok(None);
+                    }
+
+                    // delete the property also tells the owner to start the INCR transfer
+                    let reply = self.get_property(true)?;
+                    if reply.r#type() == self.atoms.incr {
+                        incr_replies = Some(vec![]);
+
+                        continue;
+                    }
+
+                    return Ok(Some(vec![reply]));
+                }
+
+                (xcb::Event::X(x::Event::PropertyNotify(event)), Some(replies)) => {
+                    if event.atom() != self.atoms.property || event.state() != x::Property::NewValue
+                    {
+                        continue;
+                    }
+
+                    let reply = self.get_property(true)?;
+
+                    // an empty chunk means the INCR transfer is done
+                    if property_bytes(&reply).is_empty() {
+                        return Ok(incr_replies);
+                    }
+
+                    replies.push(reply);
+                }
+
+                _ => {}
+            }
+        }
+    }
+
+    fn get_property(&self, delete: bool) -> Result<x::GetPropertyReply> {
+        let cookie = self.connection.send_request(&x::GetProperty {
+            delete,
+            window: self.window,
+            property: self.atoms.property,
+            r#type: x::ATOM_ANY,
+            long_offset: 0,
+            long_length: u32::MAX,
+        });
+
+        Ok(self
+            .connection
+            .wait_for_reply(cookie)
+            .tap_err(|err| error!(?err, "get property failed"))?)
+    }
+}
+
+impl Backend for X11Backend {
+    fn changes(&self) -> Receiver<Selection> {
+        self.changes.clone()
+    }
+
+    /// the owner window and the TIMESTAMP it took the selection, the owners which don't offer
+    /// the TIMESTAMP are unknown, the window alone can't tell its contents apart
+    fn owner(&mut self, selection: Selection) -> Result<Option<Owner>> {
+        let selection = selection_atom(&self.atoms, selection);
+
+        let cookie = self
+            .connection
+            .send_request(&x::GetSelectionOwner { selection });
+        let window = self.connection.wait_for_reply(cookie)?.owner();
+        if window == x::WINDOW_NONE {
+            return Ok(None);
+        }
+
+        let timestamp = self.atom("TIMESTAMP")?;
+        let timestamp = self.convert(selection, timestamp)?.and_then(|replies| {
+            replies
+                .iter()
+                .filter(|reply| reply.format() == 32)
+                .flat_map(|reply| reply.value::<u32>())
+                .next()
+                .copied()
+        });
+
+        Ok(timestamp.map(|timestamp| Owner {
+            id: window.resource_id() as u64,
+            timestamp: timestamp as u64,
+        }))
+    }
+
+    fn load_targets(&mut self, selection: Selection) -> Result<Vec<String>> {
+        let selection = selection_atom(&self.atoms, selection);
+        let targets = self.atoms.targets;
+
+        let replies = match self.convert(selection, targets)? {
+            None => return Ok(vec![]),
+            Some(replies) => replies,
+        };
+
+        replies
+            .iter()
+            .filter(|reply| reply.format() == 32)
+            .flat_map(|reply| reply.value::<Atom>())
+            .map(|atom| self.atom_name(*atom))
+            .collect()
+    }
+
+    fn load(&mut self, selection: Selection, target: &str) -> Result<Option<Vec<u8>>> {
+        let selection = selection_atom(&self.atoms, selection);
+        let target = self.atom(target)?;
+
+        Ok(self
+            .convert(selection, target)?
+            .map(|replies| replies.iter().flat_map(property_bytes).collect::<Vec<_>>())
+            .filter(|content| !content.is_empty()))
+    }
+
+    fn store(&mut self, selection: Selection, targets: Arc<[Target]>) -> Result<()> {
+        let selection = selection_atom(&self.atoms, selection);
+        let targets = targets
+            .iter()
+            .map(|target| Ok((self.atom(&target.name)?, target.data.clone())))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.owned.lock().unwrap().insert(selection, targets);
+
+        self.connection
+            .send_and_check_request(&x::SetSelectionOwner {
+                owner: self.window,
+                selection,
+                time: x::CURRENT_TIME,
+            })
+            .tap_err(|err| error!(?err, "set selection owner failed"))?;
+
+        let cookie = self
+            .connection
+            .send_request(&x::GetSelectionOwner { selection });
+        let owner = self.connection.wait_for_reply(cookie)?.owner();
+
+        if owner != self.window {
+            self.owned.lock().unwrap().remove(&selection);
+
+            return Err(anyhow!("take selection ownership failed"));
+        }
+
+        Ok(())
+    }
+
+    fn save_requests(&self) -> Receiver<Selection> {
+        self.save_requests.clone()
+    }
+
+    fn finish_save(&mut self, selection: Selection, saved: bool) {
+        if selection != Selection::Clipboard {
+            return;
+        }
+
+        let pending = mem::take(&mut *self.manager.pending.lock().unwrap());
+        for request in pending {
+            debug!(?request, saved, "respond SAVE_TARGETS request");
+
+            notify(
+                &self.connection,
+                request,
+                if saved {
+                    request.property
+                } else {
+                    x::ATOM_NONE
+                },
+            );
+        }
+    }
+
+    fn hotkey_presses(&self) -> Receiver<(f64, f64)> {
+        self.hotkey_presses.clone()
+    }
+}
+
+fn selection_atom(atoms: &Atoms, selection: Selection) -> Atom {
+    match selection {
+        Selection::Clipboard => atoms.clipboard,
+        Selection::Primary => x::ATOM_PRIMARY,
+        Selection::Secondary => x::ATOM_SECONDARY,
+    }
+}
+
+fn get_atom(connection: &Connection, name: &str) -> Result<Atom> {
+    let req = connection.send_request(&x::InternAtom {
+        only_if_exists: false,
+        name: name.as_bytes(),
+    });
+
+    Ok(connection
+        .wait_for_reply(req)
+        .tap_err(|err| error!(?err, name, "get atom failed"))?
+        .atom())
+}
+
+/// the raw bytes of the property value whatever its format is
+fn property_bytes(reply: &x::GetPropertyReply) -> Vec<u8> {
+    match reply.format() {
+        8 => reply.value::<u8>().to_vec(),
+        16 => reply
+            .value::<u16>()
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect(),
+        32 => reply
+            .value::<u32>()
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// send the selections to the returned receiver periodically, the receiver won't be filled up
+/// when the loads are slower than the poll
+fn poll_selections(selections: Vec<Selection>, interval: Duration) -> Receiver<Selection> {
+    let (sender, receiver) = crossbeam_channel::bounded(selections.len());
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        for selection in &selections {
+            if let Err(crossbeam_channel::TrySendError::Disconnected(_)) =
+                sender.try_send(*selection)
+            {
+                return;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn handle_events(
+    connection: &Connection,
+    window: Window,
+    atoms: Atoms,
+    owned: &Owned,
+    manager: &Manager,
+    hotkey: Option<&HotkeyGrab>,
+    notify_sender: Sender<xcb::Event>,
+) {
+    let mut transfers = Transfers::new();
+
+    loop {
+        let event = match connection.wait_for_event() {
+            Err(xcb::Error::Protocol(err)) => {
+                // the requestor may be gone before we respond it
+                debug!(?err, "x11 protocol error");
+
+                continue;
+            }
+
+            Err(err) => {
+                error!(?err, "wait x11 event failed");
+
+                return;
+            }
+
+            Ok(event) => event,
+        };
+
+        match event {
+            xcb::Event::X(x::Event::SelectionRequest(event))
+                if event.selection() == atoms.clipboard_manager =>
+            {
+                serve_manager(connection, atoms, manager, &event);
+            }
+
+            xcb::Event::X(x::Event::SelectionRequest(event)) => {
+                serve(connection, atoms, owned, &mut transfers, &event);
+            }
+
+            xcb::Event::X(x::Event::SelectionClear(event)) => {
+                debug!(selection = ?event.selection(), "lost selection ownership");
+
+                owned.lock().unwrap().remove(&event.selection());
+            }
+
+            xcb::Event::X(x::Event::SelectionNotify(ref notify))
+                if notify.requestor() == window =>
+            {
+                let _ = notify_sender.send(event);
+            }
+
+            // the requestor took the last chunk of an INCR transfer, it may be our window when
+            // the selections are polled
+            xcb::Event::X(x::Event::PropertyNotify(ref notify))
+                if notify.state() == x::Property::Delete
+                    && transfers.contains_key(&(notify.window(), notify.atom())) =>
+            {
+                let key = (notify.window(), notify.atom());
+                let sending = transfers
+                    .get_mut(&key)
+                    .is_some_and(|transfer| send_chunk(connection, key, transfer));
+                if !sending {
+                    transfers.remove(&key);
+                }
+            }
+
+            xcb::Event::X(x::Event::PropertyNotify(ref notify)) if notify.window() == window => {
+                let _ = notify_sender.send(event);
+            }
+
+            xcb::Event::X(x::Event::KeyPress(press)) => {
+                if let Some(hotkey) = hotkey.filter(|hotkey| hotkey.matches(&press)) {
+                    debug!("hotkey pressed");
+
+                    let _ = hotkey
+                        .press_sender
+                        .send((press.root_x() as f64, press.root_y() as f64));
+                }
+            }
+
+            _ => {}
+        }
+    }
+}
+
+/// respond the SelectionRequest with the content we own, the content larger than a request is
+/// sent by INCR
+fn serve(
+    connection: &Connection,
+    atoms: Atoms,
+    owned: &Owned,
+    transfers: &mut Transfers,
+    event: &x::SelectionRequestEvent,
+) {
+    let request = Request::from(event);
+    let property = request.property;
+
+    // the requestors which are gone never take the rest chunks
+    transfers.retain(|_, transfer| transfer.updated.elapsed() < INCR_TIMEOUT);
+
+    let owned = owned.lock().unwrap();
+    let targets = owned.get(&event.selection());
+
+    let property = match targets {
+        Some(targets) if event.target() == atoms.targets => {
+            let target_atoms = iter::once(atoms.targets)
+                .chain(targets.iter().map(|(target, _)| *target))
+                .collect::<Vec<_>>();
+
+            connection.send_request(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window: event.requestor(),
+                property,
+                r#type: x::ATOM_ATOM,
+                data: &target_atoms,
+            });
+
+            property
+        }
+
+        Some(targets) => match targets.iter().find(|(target, _)| *target == event.target()) {
+            Some((target, data)) => {
+                let data = data.get();
+
+                if data.is_empty() {
+                    x::ATOM_NONE
+                } else if data.len() > max_property_size(connection) {
+                    debug!(size = data.len(), "send content by incr");
+
+                    // the requestor deletes the property to ask for the next chunk
+                    connection.send_request(&x::ChangeWindowAttributes {
+                        window: event.requestor(),
+                        value_list: &[x::Cw::EventMask(x::EventMask::PROPERTY_CHANGE)],
+                    });
+                    connection.send_request(&x::ChangeProperty {
+                        mode: x::PropMode::Replace,
+                        window: event.requestor(),
+                        property,
+                        r#type: atoms.incr,
+                        data: &[data.len() as u32],
+                    });
+                    transfers.insert(
+                        (event.requestor(), property),
+                        Transfer {
+                            target: *target,
+                            data,
+                            offset: 0,
+                            updated: Instant::now(),
+                        },
+                    );
+
+                    property
+                } else {
+                    connection.send_request(&x::ChangeProperty {
+                        mode: x::PropMode::Replace,
+                        window: event.requestor(),
+                        property,
+                        r#type: *target,
+                        data: data.as_ref(),
+                    });
+
+                    property
+                }
+            }
+
+            None => x::ATOM_NONE,
+        },
+
+        None => x::ATOM_NONE,
+    };
+    drop(owned);
+
+    notify(connection, request, property);
+}
+
+/// send the next chunk of the INCR `transfer` to the requestor property, the empty chunk ends
+/// it, return false when it is ended
+fn send_chunk(
+    connection: &Connection,
+    (requestor, property): (Window, Atom),
+    transfer: &mut Transfer,
+) -> bool {
+    let end = (transfer.offset + max_property_size(connection)).min(transfer.data.len());
+    connection.send_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window: requestor,
+        property,
+        r#type: transfer.target,
+        data: &transfer.data[transfer.offset..end],
+    });
+
+    if let Err(err) = connection.flush() {
+        error!(?err, "flush x11 connection failed");
+    }
+
+    let ended = transfer.offset == end;
+    transfer.offset = end;
+    transfer.updated = Instant::now();
+
+    !ended
+}
+
+/// the largest property data sent by one request
+fn max_property_size(connection: &Connection) -> usize {
+    connection.get_maximum_request_length() as usize * 4 - 24
+}
+
+/// respond the SelectionRequest of CLIPBOARD_MANAGER, the SAVE_TARGETS request is responded by
+/// [`X11Backend::finish_save`] after the CLIPBOARD is saved
+fn serve_manager(
+    connection: &Connection,
+    atoms: Atoms,
+    manager: &Manager,
+    event: &x::SelectionRequestEvent,
+) {
+    let request = Request::from(event);
+
+    if request.target == atoms.save_targets {
+        debug!(requestor = ?request.requestor, "clipboard owner asks to save targets");
+
+        manager.pending.lock().unwrap().push(request);
+        let _ = manager.save_sender.send(Selection::Clipboard);
+
+        return;
+    }
+
+    let property = if request.target == atoms.targets {
+        connection.send_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: request.requestor,
+            property: request.property,
+            r#type: x::ATOM_ATOM,
+            data: &[atoms.targets, atoms.save_targets],
+        });
+
+        request.property
+    } else {
+        x::ATOM_NONE
+    };
+
+    notify(connection, request, property);
+}
+
+/// send the SelectionNotify to the requestor, the None `property` means the request is refused
+fn notify(connection: &Connection, request: Request, property: Atom) {
+    connection.send_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(request.requestor),
+        event_mask: x::EventMask::empty(),
+        event: &x::SelectionNotifyEvent::new(
+            request.time,
+            request.requestor,
+            request.selection,
+            request.target,
+            property,
+        ),
+    });
+
+    if let Err(err) = connection.flush() {
+        error!(?err, "flush x11 connection failed");
+    }
+}
+
+/// take the CLIPBOARD_MANAGER selection unless another clipboard manager owns it
+fn become_clipboard_manager(connection: &Connection, window: Window, atoms: Atoms) -> Result<()> {
+    let cookie = connection.send_request(&x::GetSelectionOwner {
+        selection: atoms.clipboard_manager,
+    });
+    let owner = connection.wait_for_reply(cookie)?.owner();
+
+    if owner != x::WINDOW_NONE {
+        info!(?owner, "another clipboard manager is running");
+
+        return Ok(());
+    }
+
+    connection.send_and_check_request(&x::SetSelectionOwner {
+        owner: window,
+        selection: atoms.clipboard_manager,
+        time: x::CURRENT_TIME,
+    })?;
+
+    Ok(())
+}
+
+/// grab the `hotkey` by a connection of its own, the presses are sent to the returned receiver.
+/// it is used by the window attached to the daemon, which has no backend
+pub fn watch_hotkey(hotkey: &Hotkey) -> Result<Receiver<(f64, f64)>> {
+    let (connection, screen_num) =
+        Connection::connect(None).tap_err(|err| error!(?err, "connect x11 server failed"))?;
+    let root = connection
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| anyhow!("screen {} not found", screen_num))?
+        .root();
+
+    let (press_sender, presses) = crossbeam_channel::unbounded();
+    let grab = grab_hotkey(&connection, root, hotkey, press_sender)?;
+
+    thread::spawn(move || loop {
+        match connection.wait_for_event() {
+            Err(xcb::Error::Protocol(err)) => debug!(?err, "x11 protocol error"),
+
+            Err(err) => {
+                error!(?err, "wait x11 event failed");
+
+                return;
+            }
+
+            Ok(xcb::Event::X(x::Event::KeyPress(press))) if grab.matches(&press) => {
+                debug!("hotkey pressed");
+
+                let position = (press.root_x() as f64, press.root_y() as f64);
+                if grab.press_sender.send(position).is_err() {
+                    return;
+                }
+            }
+
+            Ok(_) => {}
+        }
+    });
+
+    Ok(presses)
+}
+
+/// grab the `hotkey` on the `root` window, every keycode producing the key is grabbed
+fn grab_hotkey(
+    connection: &Connection,
+    root: Window,
+    hotkey: &Hotkey,
+    press_sender: Sender<(f64, f64)>,
+) -> Result<HotkeyGrab> {
+    let keysym = keysym(&hotkey.key).ok_or_else(|| anyhow!("unknown key {}", hotkey.key))?;
+
+    let setup = connection.get_setup();
+    let (min_keycode, max_keycode) = (setup.min_keycode(), setup.max_keycode());
+    let cookie = connection.send_request(&x::GetKeyboardMapping {
+        first_keycode: min_keycode,
+        count: max_keycode - min_keycode + 1,
+    });
+    let mapping = connection.wait_for_reply(cookie)?;
+
+    let keycodes = mapping
+        .keysyms()
+        .chunks(mapping.keysyms_per_keycode().max(1) as usize)
+        .zip(min_keycode..=max_keycode)
+        .filter(|(keysyms, _)| keysyms.contains(&keysym))
+        .map(|(_, keycode)| keycode)
+        .collect::<Vec<_>>();
+    if keycodes.is_empty() {
+        return Err(anyhow!("no keycode produces {}", hotkey.key));
+    }
+
+    let modifiers = hotkey
+        .modifiers
+        .iter()
+        .fold(x::ModMask::empty(), |mask, modifier| {
+            mask | match modifier {
+                Modifier::Shift => x::ModMask::SHIFT,
+                Modifier::Ctrl => x::ModMask::CONTROL,
+                Modifier::Alt => x::ModMask::N1,
+                Modifier::Super => x::ModMask::N4,
+            }
+        });
+
+    for &keycode in &keycodes {
+        // grab with CapsLock and NumLock too, otherwise the hotkey doesn't work when they are on
+        for locks in LOCK_MODIFIERS {
+            connection
+                .send_and_check_request(&x::GrabKey {
+                    owner_events: false,
+                    grab_window: root,
+                    modifiers: modifiers | locks,
+                    key: keycode,
+                    pointer_mode: x::GrabMode::Async,
+                    keyboard_mode: x::GrabMode::Async,
+                })
+                .map_err(|err| anyhow!("the hotkey is used by another app? {:?}", err))?;
+        }
+    }
+
+    info!(?keycodes, "hotkey grabbed");
+
+    Ok(HotkeyGrab {
+        keycodes,
+        modifiers,
+        press_sender,
+    })
+}
+
+impl HotkeyGrab {
+    fn matches(&self, press: &x::KeyPressEvent) -> bool {
+        let state = x::ModMask::from_bits_truncate(press.state().bits());
+        let ignored = LOCK_MODIFIERS
+            .iter()
+            .fold(x::ModMask::empty(), |mask, locks| mask | *locks);
+
+        self.keycodes.contains(&press.detail()) && state.difference(ignored) == self.modifiers
+    }
+}
+
+/// the X keysym of the key name accepted by the config
+fn keysym(key: &str) -> Option<x::Keysym> {
+    let keysym = match key {
+        "space" => 0x20,
+        "grave" => 0x60,
+        "tab" => 0xff09,
+        "return" => 0xff0d,
+        "escape" => 0xff1b,
+        "home" => 0xff50,
+        "end" => 0xff57,
+        "insert" => 0xff63,
+        "delete" => 0xffff,
+        // F1 is 0xffbe, the others follow it
+        key if key.len() > 1 && key.starts_with('f') => 0xffbe + key[1..].parse::<u32>().ok()? - 1,
+        // the latin1 keysyms are the same as the lowercase ascii
+        key if key.len() == 1 && key.is_ascii() => key.as_bytes()[0] as u32,
+        _ => return None,
+    };
+
+    Some(keysym)
+}
@@ -0,0 +1,118 @@
+//! watch the selection owner changes by the XFixes extension, so we only need to fetch the
+//! selection content when it really changes
+
+use std::collections::HashMap;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Receiver;
+use tap::TapFallible;
+use tracing::{debug, error, info};
+use xcb::x::{Atom, Timestamp, Window};
+use xcb::{xfixes, Connection, Extension, Xid};
+
+use super::Selection;
+
+/// spawn a thread to watch the owner changes of the `selections`, the changed selection will be
+/// sent to the returned receiver
+///
+/// the owner changes caused by `ignore_owner` are ignored, it should be the window we use to
+/// store the selection content. None will be returned when the X server doesn't support XFixes
+pub fn watch(
+    selections: &[(Selection, Atom)],
+    ignore_owner: Window,
+) -> Result<Option<Receiver<Selection>>> {
+    let (connection, screen_num) =
+        Connection::connect_with_extensions(None, &[], &[Extension::XFixes])
+            .tap_err(|err| error!(?err, "connect x11 server for xfixes failed"))?;
+
+    if !connection
+        .active_extensions()
+        .any(|extension| extension == Extension::XFixes)
+    {
+        info!("x11 server doesn't support xfixes");
+
+        return Ok(None);
+    }
+
+    // the XFixes requires the client to announce its version before using it
+    let cookie = connection.send_request(&xfixes::QueryVersion {
+        client_major_version: 5,
+        client_minor_version: 0,
+    });
+    connection
+        .wait_for_reply(cookie)
+        .tap_err(|err| error!(?err, "query xfixes version failed"))?;
+
+    let root = connection
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| anyhow!("screen {} not found", screen_num))?
+        .root();
+
+    for (selection, atom) in selections {
+        connection
+            .send_and_check_request(&xfixes::SelectSelectionInput {
+                window: root,
+                selection: *atom,
+                event_mask: xfixes::SelectionEventMask::SET_SELECTION_OWNER
+                    | xfixes::SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | xfixes::SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            })
+            .tap_err(|err| error!(?err, ?selection, "select xfixes selection input failed"))?;
+    }
+
+    let selections = selections
+        .iter()
+        .map(|(selection, atom)| (*atom, *selection))
+        .collect::<HashMap<_, _>>();
+
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    thread::spawn(move || {
+        let mut owners: HashMap<Atom, (Window, Timestamp)> = HashMap::new();
+
+        loop {
+            let event = match connection.wait_for_event() {
+                Err(err) => {
+                    error!(?err, "wait xfixes event failed");
+
+                    return;
+                }
+
+                Ok(event) => event,
+            };
+
+            let event = match event {
+                xcb::Event::XFixes(xfixes::Event::SelectionNotify(event)) => event,
+                _ => continue,
+            };
+
+            let selection = match selections.get(&event.selection()) {
+                None => continue,
+                Some(selection) => *selection,
+            };
+
+            let owner = (event.owner(), event.selection_timestamp());
+            if owners.insert(event.selection(), owner) == Some(owner) {
+                continue;
+            }
+
+            // the selection is gone with its owner or is stored by ourselves, nothing to fetch
+            if event.owner().is_none() || event.owner() == ignore_owner {
+                continue;
+            }
+
+            debug!(?selection, "selection owner changed");
+
+            if sender.send(selection).is_err() {
+                debug!("selection change receiver closed, stop watching");
+
+                return;
+            }
+        }
+    });
+
+    Ok(Some(receiver))
+}
@@ -0,0 +1,521 @@
+//! the config file `$XDG_CONFIG_HOME/history_clipboard/config.toml`, every field is optional and
+//! the defaults are the built-in behaviour
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use std::{env, fmt, fs, io, thread};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Receiver;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::clipboard::{IgnoreRules, Limits, Selection};
+
+const DIR_NAME: &str = "history_clipboard";
+const FILE_NAME: &str = "config.toml";
+
+const MAX_HISTORY_SIZE: usize = 10_000;
+const MIN_WINDOW_SIZE: f64 = 100.0;
+const MIN_POLL_INTERVAL_MS: u64 = 10;
+const MAX_LOAD_TIMEOUT_MS: u64 = 10_000;
+/// how often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub history: HistoryConfig,
+    pub window: WindowConfig,
+    pub clipboard: ClipboardConfig,
+    pub ignore: IgnoreConfig,
+    pub theme: ThemeConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub max_size: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { max_size: 20 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: String,
+    pub width: f64,
+    pub height: f64,
+    /// the position of the top left corner, None lets the window manager place the window
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    /// the global key combination to pop up the window near the pointer, it is X11 only
+    pub hotkey: Option<Hotkey>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "History Clipboard".to_string(),
+            width: 350.0,
+            height: 500.0,
+            x: None,
+            y: None,
+            hotkey: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    /// the selections to capture, the secondary is X11 only
+    pub selections: Vec<Selection>,
+    /// the interval to poll the X11 selections when the XFixes is not supported
+    pub poll_interval_ms: u64,
+    /// how long to wait the selection owner to send the content, it is reset by every chunk
+    pub load_timeout_ms: u64,
+    /// the target larger than it in bytes is not captured
+    pub max_target_size: usize,
+    /// the targets are not captured when their total size in bytes exceeds it
+    pub max_total_size: usize,
+}
+
+impl ClipboardConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+
+    pub fn load_timeout(&self) -> Duration {
+        Duration::from_millis(self.load_timeout_ms)
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_target_size: self.max_target_size,
+            max_total_size: self.max_total_size,
+        }
+    }
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            selections: vec![Selection::Clipboard, Selection::Primary],
+            poll_interval_ms: 50,
+            load_timeout_ms: 50,
+            max_target_size: Limits::default().max_target_size,
+            max_total_size: Limits::default().max_total_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IgnoreConfig {
+    /// the contents offering any of the targets are not captured
+    pub targets: Vec<String>,
+    /// the texts matching any of the regex patterns are not captured
+    pub text_patterns: Vec<String>,
+}
+
+impl IgnoreConfig {
+    pub fn rules(&self) -> IgnoreRules {
+        IgnoreRules {
+            targets: self.targets.clone(),
+            // the patterns are checked by the validation
+            text_patterns: self
+                .text_patterns
+                .iter()
+                .filter_map(|pattern| Regex::new(pattern).ok())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub background: Rgba,
+    /// the background of the buttons and the radios
+    pub button: Rgba,
+    /// the border of the hovered and chosen buttons
+    pub accent: Rgba,
+    pub text: Rgba,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            background: Rgba(242, 242, 242, 255),
+            button: Rgba(251, 251, 251, 255),
+            accent: Rgba(46, 179, 152, 255),
+            text: Rgba(0, 0, 0, 255),
+        }
+    }
+}
+
+/// the color written as `#rrggbb` or `#rrggbbaa`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
+
+impl TryFrom<String> for Rgba {
+    type Error = InvalidColor;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| matches!(hex.len(), 6 | 8) && hex.is_ascii())
+            .ok_or_else(|| InvalidColor(s.clone()))?;
+
+        let component = |i: usize| {
+            u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| InvalidColor(s.clone()))
+        };
+        let alpha = if hex.len() == 8 { component(3)? } else { 255 };
+
+        Ok(Self(component(0)?, component(1)?, component(2)?, alpha))
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidColor(String);
+
+impl fmt::Display for InvalidColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid color {:?}, expect #rrggbb or #rrggbbaa", self.0)
+    }
+}
+
+/// the key combination written as `Super+V`, the modifiers are Shift, Ctrl, Alt and Super, and
+/// the key is a letter, a digit, F1 to F12 or one of [`NAMED_KEYS`]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Hotkey {
+    pub modifiers: Vec<Modifier>,
+    /// the lowercase key name
+    pub key: String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    Alt,
+    Super,
+}
+
+pub const NAMED_KEYS: &[&str] = &[
+    "space", "tab", "return", "escape", "insert", "delete", "home", "end", "grave",
+];
+
+impl TryFrom<String> for Hotkey {
+    type Error = InvalidHotkey;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || InvalidHotkey(s.clone());
+
+        let mut parts = s.split('+').map(|part| part.trim().to_lowercase());
+        let key = parts.next_back().ok_or_else(invalid)?;
+
+        let function_key = key
+            .strip_prefix('f')
+            .and_then(|n| n.parse::<u8>().ok())
+            .is_some_and(|n| (1..=12).contains(&n));
+        let valid_key = (key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric()))
+            || function_key
+            || NAMED_KEYS.contains(&key.as_str());
+        if !valid_key {
+            return Err(invalid());
+        }
+
+        let modifiers = parts
+            .map(|part| match part.as_str() {
+                "shift" => Ok(Modifier::Shift),
+                "ctrl" | "control" => Ok(Modifier::Ctrl),
+                "alt" => Ok(Modifier::Alt),
+                "super" => Ok(Modifier::Super),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { modifiers, key })
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidHotkey(String);
+
+impl fmt::Display for InvalidHotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hotkey {:?}, expect like Super+V", self.0)
+    }
+}
+
+impl Config {
+    /// the config file path, None when neither XDG_CONFIG_HOME nor HOME is set
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|config_home| config_home.join(DIR_NAME).join(FILE_NAME))
+    }
+
+    /// load the config file, the defaults are used when it doesn't exist
+    pub fn load() -> Result<Self> {
+        match Self::path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(anyhow!("read config {} failed: {}", path.display(), err)),
+            Ok(text) => text,
+        };
+
+        Self::parse(&text).map_err(|err| anyhow!("invalid config {}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let config = toml::from_str::<Self>(text)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(1..=MAX_HISTORY_SIZE).contains(&self.history.max_size) {
+            return Err(anyhow!(
+                "history.max_size must be between 1 and {}, got {}",
+                MAX_HISTORY_SIZE,
+                self.history.max_size
+            ));
+        }
+
+        for (name, size) in [
+            ("window.width", self.window.width),
+            ("window.height", self.window.height),
+        ] {
+            if !size.is_finite() || size < MIN_WINDOW_SIZE {
+                return Err(anyhow!(
+                    "{} must be at least {}, got {}",
+                    name,
+                    MIN_WINDOW_SIZE,
+                    size
+                ));
+            }
+        }
+
+        for (name, position) in [("window.x", self.window.x), ("window.y", self.window.y)] {
+            if position.is_some_and(|position| !position.is_finite()) {
+                return Err(anyhow!("{} must be a finite number", name));
+            }
+        }
+
+        if self.clipboard.selections.is_empty() {
+            return Err(anyhow!("clipboard.selections must not be empty"));
+        }
+        for (i, selection) in self.clipboard.selections.iter().enumerate() {
+            if self.clipboard.selections[..i].contains(selection) {
+                return Err(anyhow!(
+                    "clipboard.selections has duplicated {:?}",
+                    selection
+                ));
+            }
+        }
+
+        if self.clipboard.poll_interval_ms < MIN_POLL_INTERVAL_MS {
+            return Err(anyhow!(
+                "clipboard.poll_interval_ms must be at least {}, got {}",
+                MIN_POLL_INTERVAL_MS,
+                self.clipboard.poll_interval_ms
+            ));
+        }
+
+        if !(1..=MAX_LOAD_TIMEOUT_MS).contains(&self.clipboard.load_timeout_ms) {
+            return Err(anyhow!(
+                "clipboard.load_timeout_ms must be between 1 and {}, got {}",
+                MAX_LOAD_TIMEOUT_MS,
+                self.clipboard.load_timeout_ms
+            ));
+        }
+
+        for (name, size) in [
+            ("clipboard.max_target_size", self.clipboard.max_target_size),
+            ("clipboard.max_total_size", self.clipboard.max_total_size),
+        ] {
+            if size == 0 {
+                return Err(anyhow!("{} must be positive", name));
+            }
+        }
+        if self.clipboard.max_target_size > self.clipboard.max_total_size {
+            return Err(anyhow!(
+                "clipboard.max_target_size must not exceed clipboard.max_total_size, got {} > {}",
+                self.clipboard.max_target_size,
+                self.clipboard.max_total_size
+            ));
+        }
+
+        for pattern in &self.ignore.text_patterns {
+            if let Err(err) = Regex::new(pattern) {
+                return Err(anyhow!(
+                    "ignore.text_patterns has invalid pattern {:?}: {}",
+                    pattern,
+                    err
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// send the config when the file at `path` is changed, the invalid one is logged and skipped.
+/// the window, the hotkey, the selections, the poll interval and the load timeout are not
+/// reloaded, they are applied at the next start
+pub fn watch(path: PathBuf, mut current: Config) -> Receiver<Config> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    thread::spawn(move || {
+        let mut last_modified = modified(&path);
+
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+
+            let modified = modified(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            match Config::load_from(&path) {
+                Err(err) => error!(%err, "reload config failed, keep the current one"),
+
+                Ok(config) if config == current => {}
+
+                Ok(config) => {
+                    info!("reload config done");
+
+                    current = config.clone();
+                    if sender.send(config).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+/// None when the file doesn't exist
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = Config::parse(
+            r##"
+                [history]
+                max_size = 100
+
+                [window]
+                title = "Clips"
+                width = 400
+                x = 10
+                hotkey = "Super+Shift+v"
+
+                [clipboard]
+                selections = ["clipboard", "secondary"]
+
+                [theme]
+                accent = "#ff000080"
+                text = "#333333"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.history.max_size, 100);
+        assert_eq!(config.window.title, "Clips");
+        assert_eq!(config.window.width, 400.0);
+        assert_eq!(config.window.height, WindowConfig::default().height);
+        assert_eq!(config.window.x, Some(10.0));
+        assert_eq!(config.window.y, None);
+        assert_eq!(
+            config.window.hotkey,
+            Some(Hotkey {
+                modifiers: vec![Modifier::Super, Modifier::Shift],
+                key: "v".to_string(),
+            })
+        );
+        assert_eq!(
+            config.clipboard.selections,
+            vec![Selection::Clipboard, Selection::Secondary]
+        );
+        assert_eq!(
+            config.clipboard.poll_interval_ms,
+            ClipboardConfig::default().poll_interval_ms
+        );
+        assert_eq!(config.theme.accent, Rgba(255, 0, 0, 128));
+        assert_eq!(config.theme.text, Rgba(0x33, 0x33, 0x33, 255));
+    }
+
+    #[test]
+    fn reject_invalid_config() {
+        for (text, message) in [
+            ("[history]\nmax_size = 0", "history.max_size"),
+            ("[window]\nheight = 10", "window.height"),
+            (
+                "[clipboard]\npoll_interval_ms = 1",
+                "clipboard.poll_interval_ms",
+            ),
+            ("[clipboard]\nselections = []", "clipboard.selections"),
+            (
+                "[clipboard]\nselections = [\"primary\", \"primary\"]",
+                "clipboard.selections",
+            ),
+            ("[clipboard]\nselections = [\"other\"]", "unknown variant"),
+            (
+                "[clipboard]\nload_timeout_ms = 0",
+                "clipboard.load_timeout_ms",
+            ),
+            (
+                "[clipboard]\nmax_total_size = 0",
+                "clipboard.max_total_size",
+            ),
+            (
+                "[clipboard]\nmax_target_size = 2048\nmax_total_size = 1024",
+                "clipboard.max_target_size",
+            ),
+            ("[theme]\ntext = \"black\"", "invalid color"),
+            ("[window]\nhotkey = \"Super+F13\"", "invalid hotkey"),
+            ("[window]\nhotkey = \"Meta+V\"", "invalid hotkey"),
+            ("[ignore]\ntext_patterns = [\"(\"]", "ignore.text_patterns"),
+            ("[history]\nsize = 10", "unknown field"),
+        ] {
+            let err = Config::parse(text).unwrap_err().to_string();
+            assert!(err.contains(message), "{:?} gives {:?}", text, err);
+        }
+    }
+}
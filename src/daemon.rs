@@ -0,0 +1,425 @@
+//! the headless instance, it captures the clipboard and keeps the history without a window. the
+//! cli and the gui reach it by the ipc socket, the gui shows its history instead of capturing
+
+use anyhow::Result;
+use crossbeam_channel::{select, Receiver, Sender};
+use druid::im::Vector;
+use druid::Data;
+use tap::TapFallible;
+use tracing::{error, info};
+
+use crate::clipboard::{self, Entry, Removed};
+use crate::config::Config;
+use crate::ipc::{self, HistoryStatus, Request, Response};
+use crate::storage::{self, Command};
+
+pub fn run() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let config = Config::load().tap_err(|err| error!(%err, "load config failed"))?;
+
+    let lock = match ipc::lock_instance() {
+        Err(err) if err.is::<ipc::AlreadyRunning>() => {
+            info!("another instance is running, exit");
+            return Ok(());
+        }
+        result => result?,
+    };
+
+    // the daemon is useless when nobody can reach it
+    let (call_sender, call_receiver) = crossbeam_channel::unbounded();
+    ipc::serve(&lock, call_sender).tap_err(|err| error!(%err, "serve ipc failed"))?;
+
+    let (storage_sender, storage_event_receiver, persisted) = crate::spawn_storage();
+
+    let (new_content_sender, new_content_receiver) = crossbeam_channel::unbounded();
+    let (content_sender, content_receiver) = crossbeam_channel::unbounded();
+    let (config_sender, config_receiver) = crossbeam_channel::unbounded();
+    let (ignore_receiver, limits_receiver) = crate::watch_config(&config, config_sender);
+    let (removed_sender, removed_receiver) = crossbeam_channel::unbounded();
+    // the hotkey pops up a window, the daemon has none
+    crate::spawn_clipboard(
+        &config,
+        None,
+        content_sender,
+        new_content_receiver,
+        ignore_receiver,
+        limits_receiver,
+        removed_receiver,
+    )?;
+
+    info!("daemon started");
+
+    let daemon = Daemon {
+        contents: Vector::new(),
+        max_size: config.history.max_size,
+        status: if persisted {
+            HistoryStatus::Opening
+        } else {
+            HistoryStatus::Disabled
+        },
+        storage_sender,
+        restore_sender: new_content_sender,
+        removed_sender,
+        subscribers: vec![],
+        unlock_replies: vec![],
+    };
+    daemon.run(
+        content_receiver,
+        call_receiver,
+        storage_event_receiver,
+        config_receiver,
+    );
+
+    Ok(())
+}
+
+/// the history state, it is changed like the gui one
+struct Daemon {
+    contents: Vector<Entry>,
+    max_size: usize,
+    status: HistoryStatus,
+    storage_sender: Sender<Command>,
+    restore_sender: Sender<Entry>,
+    removed_sender: Sender<Removed>,
+    /// the connections waiting the history changes
+    subscribers: Vec<Sender<(Response, Vec<u8>)>>,
+    /// the unlock requests waiting the storage answers
+    unlock_replies: Vec<Sender<(Response, Vec<u8>)>>,
+}
+
+impl Daemon {
+    fn run(
+        mut self,
+        content_receiver: Receiver<Entry>,
+        call_receiver: Receiver<ipc::Call>,
+        mut storage_event_receiver: Receiver<storage::Event>,
+        mut config_receiver: Receiver<Config>,
+    ) {
+        loop {
+            let old_contents = self.contents.clone();
+            let old_status = self.status.clone();
+
+            select! {
+                recv(content_receiver) -> entry => match entry {
+                    Ok(entry) => clipboard::push_history(&mut self.contents, entry, self.max_size),
+
+                    Err(_) => {
+                        error!("the clipboard is stopped, exit");
+                        return;
+                    }
+                },
+
+                recv(call_receiver) -> call => match call {
+                    Ok(call) => self.handle_call(call),
+                    Err(_) => return,
+                },
+
+                recv(storage_event_receiver) -> event => match event {
+                    Ok(event) => self.update_lock(event),
+                    // the storage is not running when the history is not persisted
+                    Err(_) => storage_event_receiver = crossbeam_channel::never(),
+                },
+
+                recv(config_receiver) -> config => match config {
+                    Ok(config) => {
+                        self.max_size = config.history.max_size;
+                        self.truncate();
+                    }
+
+                    // the config is not watched anymore
+                    Err(_) => config_receiver = crossbeam_channel::never(),
+                },
+            }
+
+            let contents_changed = !self.contents.same(&old_contents);
+
+            // the contents are cleared when locking, they must not overwrite the saved history
+            if contents_changed && self.status.is_open() {
+                let _ = self
+                    .storage_sender
+                    .send(Command::Save(self.contents.clone()));
+            }
+
+            if contents_changed || self.status != old_status {
+                self.publish();
+            }
+        }
+    }
+
+    fn handle_call(&mut self, call: ipc::Call) {
+        let reply = match call.request {
+            Request::Subscribe => {
+                let _ = call.reply_sender.send(self.history());
+                self.subscribers.push(call.reply_sender);
+
+                return;
+            }
+
+            Request::Unlock { passphrase } => match &mut self.status {
+                HistoryStatus::Locked { error, .. } => {
+                    // it is answered when the storage answers
+                    *error = None;
+                    let _ = self.storage_sender.send(Command::Unlock(passphrase));
+                    self.unlock_replies.push(call.reply_sender);
+
+                    return;
+                }
+
+                _ => error_reply("the history is not locked"),
+            },
+
+            Request::Lock => match self.status {
+                HistoryStatus::Unlocked => {
+                    let _ = self.storage_sender.send(Command::Lock);
+                    self.status = HistoryStatus::Opening;
+                    self.contents.clear();
+
+                    (Response::Done, vec![])
+                }
+
+                _ => error_reply("the history is not unlocked"),
+            },
+
+            _ if !self.status.is_open() => error_reply("the history is locked"),
+
+            request => ipc::handle(
+                request,
+                call.data,
+                &mut self.contents,
+                self.max_size,
+                &self.restore_sender,
+                &self.removed_sender,
+            ),
+        };
+
+        let _ = call.reply_sender.send(reply);
+    }
+
+    fn update_lock(&mut self, event: storage::Event) {
+        match event {
+            storage::Event::Locked { prompt, error } => {
+                let message = error.as_deref().unwrap_or("the history is still locked");
+                for reply_sender in self.unlock_replies.drain(..) {
+                    let _ = reply_sender.send(error_reply(message));
+                }
+
+                self.status = HistoryStatus::Locked { prompt, error };
+            }
+
+            storage::Event::Unlocked(entries) => {
+                for reply_sender in self.unlock_replies.drain(..) {
+                    let _ = reply_sender.send((Response::Done, vec![]));
+                }
+
+                self.status = HistoryStatus::Unlocked;
+
+                // the contents captured while locked are newer than the saved ones
+                clipboard::merge_history(&mut self.contents, entries, self.max_size);
+            }
+        }
+    }
+
+    fn truncate(&mut self) {
+        clipboard::truncate_history(&mut self.contents, self.max_size);
+    }
+
+    /// the entries are hidden when the history is locked
+    fn history(&self) -> (Response, Vec<u8>) {
+        let data = if self.status.is_open() {
+            storage::encode_entries(&self.contents)
+        } else {
+            storage::encode_entries(&[])
+        };
+
+        (
+            Response::History {
+                status: self.status.clone(),
+            },
+            data,
+        )
+    }
+
+    fn publish(&mut self) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        let reply = self.history();
+
+        // the closed connections are dropped
+        self.subscribers
+            .retain(|subscriber| subscriber.send(reply.clone()).is_ok());
+    }
+}
+
+fn error_reply(message: &str) -> (Response, Vec<u8>) {
+    (
+        Response::Error {
+            message: message.to_string(),
+        },
+        vec![],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::clipboard::{Content, Selection};
+
+    #[test]
+    fn publish_changes() {
+        let (content_sender, content_receiver) = crossbeam_channel::unbounded();
+        let (call_sender, call_receiver) = crossbeam_channel::unbounded();
+        let (storage_sender, storage_receiver) = crossbeam_channel::unbounded();
+        let (restore_sender, _restore_receiver) = crossbeam_channel::unbounded();
+
+        let daemon = Daemon {
+            contents: Vector::new(),
+            max_size: 2,
+            status: HistoryStatus::Unlocked,
+            storage_sender,
+            restore_sender,
+            removed_sender: crossbeam_channel::unbounded().0,
+            subscribers: vec![],
+            unlock_replies: vec![],
+        };
+        thread::spawn(move || {
+            daemon.run(
+                content_receiver,
+                call_receiver,
+                crossbeam_channel::never(),
+                crossbeam_channel::never(),
+            )
+        });
+
+        let (reply_sender, reply_receiver) = crossbeam_channel::unbounded();
+        call_sender
+            .send(ipc::Call {
+                request: Request::Subscribe,
+                data: vec![],
+                reply_sender,
+            })
+            .unwrap();
+
+        let next_texts = || match reply_receiver.recv().unwrap() {
+            (Response::History { status }, data) => {
+                assert_eq!(status, HistoryStatus::Unlocked);
+
+                storage::decode_entries(&data)
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| match entry.content {
+                        Content::Text(text) => text.to_string(),
+                        content => panic!("unexpected content {:?}", content),
+                    })
+                    .collect::<Vec<_>>()
+            }
+            response => panic!("unexpected response {:?}", response),
+        };
+
+        assert!(next_texts().is_empty());
+
+        for text in ["a", "b", "c"] {
+            let entry = Entry::from_content(Selection::Clipboard, text.to_string().into());
+            content_sender.send(entry).unwrap();
+        }
+
+        assert_eq!(next_texts(), ["a"]);
+        assert_eq!(next_texts(), ["b", "a"]);
+        assert_eq!(next_texts(), ["c", "b"]);
+
+        // the content copied again is moved to the front
+        let entry = Entry::from_content(Selection::Clipboard, "b".to_string().into());
+        content_sender.send(entry).unwrap();
+        assert_eq!(next_texts(), ["b", "c"]);
+        assert!(matches!(
+            storage_receiver.recv().unwrap(),
+            Command::Save(contents) if contents.len() == 1
+        ));
+    }
+
+    #[test]
+    fn answer_unlock_after_storage() {
+        let (storage_sender, storage_receiver) = crossbeam_channel::unbounded();
+        let mut daemon = Daemon {
+            contents: Vector::new(),
+            max_size: 10,
+            status: HistoryStatus::Locked {
+                prompt: storage::Prompt::Passphrase,
+                error: None,
+            },
+            storage_sender,
+            restore_sender: crossbeam_channel::unbounded().0,
+            removed_sender: crossbeam_channel::unbounded().0,
+            subscribers: vec![],
+            unlock_replies: vec![],
+        };
+
+        let (reply_sender, reply_receiver) = crossbeam_channel::unbounded();
+        daemon.handle_call(ipc::Call {
+            request: Request::Unlock {
+                passphrase: "wrong".to_string(),
+            },
+            data: vec![],
+            reply_sender,
+        });
+        assert!(matches!(
+            storage_receiver.try_recv(),
+            Ok(Command::Unlock(_))
+        ));
+        assert!(reply_receiver.try_recv().is_err());
+
+        daemon.update_lock(storage::Event::Locked {
+            prompt: storage::Prompt::Passphrase,
+            error: Some("wrong passphrase".to_string()),
+        });
+        assert!(matches!(
+            reply_receiver.try_recv(),
+            Ok((Response::Error { message }, _)) if message == "wrong passphrase"
+        ));
+    }
+
+    #[test]
+    fn merge_saved_entries() {
+        let mut daemon = Daemon {
+            contents: Vector::new(),
+            max_size: 10,
+            status: HistoryStatus::Locked {
+                prompt: storage::Prompt::Passphrase,
+                error: None,
+            },
+            storage_sender: crossbeam_channel::unbounded().0,
+            restore_sender: crossbeam_channel::unbounded().0,
+            removed_sender: crossbeam_channel::unbounded().0,
+            subscribers: vec![],
+            unlock_replies: vec![],
+        };
+        let new_entry =
+            |text: &str| Entry::from_content(Selection::Clipboard, text.to_string().into());
+
+        // copied while locked
+        daemon.contents.push_back(new_entry("a"));
+
+        let mut saved = new_entry("a");
+        saved.pinned = true;
+        saved.use_count = 3;
+        let saved_id = saved.id;
+        daemon.update_lock(storage::Event::Unlocked(vec![new_entry("b"), saved]));
+
+        let texts = daemon
+            .contents
+            .iter()
+            .map(|entry| match &entry.content {
+                Content::Text(text) => text.to_string(),
+                content => panic!("unexpected content {:?}", content),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["a", "b"]);
+        assert_eq!(daemon.contents[0].id, saved_id);
+        assert!(daemon.contents[0].pinned);
+        assert_eq!(daemon.contents[0].use_count, 4);
+    }
+}
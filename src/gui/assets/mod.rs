@@ -1,3 +1,6 @@
 pub static TEXT_SVG: &str = include_str!("text.svg");
 pub static IMAGE_SVG: &str = include_str!("image.svg");
 pub static ALL_SVG: &str = include_str!("all.svg");
+pub static FILE_SVG: &str = include_str!("file.svg");
+pub static FOLDER_SVG: &str = include_str!("folder.svg");
+pub static PIN_SVG: &str = include_str!("pin.svg");
@@ -0,0 +1,94 @@
+//! the mouse and keyboard handling shared by the custom button and radio
+
+use druid::{Env, Event, EventCtx, KbKey, KeyEvent, LifeCycle, LifeCycleCtx};
+use tracing::trace;
+
+pub type ClickAction<T> = dyn Fn(&mut EventCtx, &mut T, &Env);
+
+/// call `on_click` when the widget is clicked, or Enter or Space is pressed on the focused one,
+/// and move the focus by the keys. return true if the event is handled, it should not be passed
+/// to the child then
+pub fn event<T>(
+    ctx: &mut EventCtx,
+    event: &Event,
+    data: &mut T,
+    env: &Env,
+    on_click: Option<&ClickAction<T>>,
+) -> bool {
+    match event {
+        Event::MouseDown(_) => {
+            if !ctx.is_disabled() {
+                ctx.set_active(true);
+                ctx.request_paint();
+                trace!("Button {:?} pressed", ctx.widget_id());
+            }
+
+            false
+        }
+
+        Event::MouseUp(_) => {
+            if ctx.is_active() && !ctx.is_disabled() {
+                ctx.request_paint();
+                trace!("Button {:?} released", ctx.widget_id());
+
+                if ctx.is_hot() {
+                    if let Some(on_click) = on_click {
+                        on_click(ctx, data, env);
+                    }
+                }
+            }
+            ctx.set_active(false);
+
+            false
+        }
+
+        Event::KeyDown(key) if ctx.is_focused() && !ctx.is_disabled() => {
+            if is_activating(key) {
+                if let Some(on_click) = on_click {
+                    on_click(ctx, data, env);
+                }
+                ctx.set_handled();
+
+                return true;
+            }
+
+            move_focus(ctx, key)
+        }
+
+        _ => false,
+    }
+}
+
+/// repaint when the look changes, and take part in the focus chain
+pub fn lifecycle(ctx: &mut LifeCycleCtx, event: &LifeCycle) {
+    match event {
+        LifeCycle::HotChanged(_) | LifeCycle::DisabledChanged(_) | LifeCycle::FocusChanged(_) => {
+            ctx.request_paint()
+        }
+        LifeCycle::BuildFocusChain => ctx.register_for_focus(),
+        _ => {}
+    }
+}
+
+/// Enter and Space click the focused widget
+fn is_activating(key: &KeyEvent) -> bool {
+    match &key.key {
+        KbKey::Enter => true,
+        KbKey::Character(c) => c == " ",
+        _ => false,
+    }
+}
+
+/// move the focus by the arrows, or j and k like vim, the event is handled when it is moved
+fn move_focus(ctx: &mut EventCtx, key: &KeyEvent) -> bool {
+    match &key.key {
+        KbKey::ArrowDown | KbKey::ArrowRight => ctx.focus_next(),
+        KbKey::ArrowUp | KbKey::ArrowLeft => ctx.focus_prev(),
+        KbKey::Character(c) if c == "j" => ctx.focus_next(),
+        KbKey::Character(c) if c == "k" => ctx.focus_prev(),
+        _ => return false,
+    }
+    ctx.set_handled();
+
+    true
+}
@@ -0,0 +1,35 @@
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, Menu, Widget};
+
+use super::Clipboard;
+
+/// show the menu made from the data when the widget is right clicked
+pub struct ContextMenu<T> {
+    make_menu: Box<dyn Fn(&T) -> Option<Menu<Clipboard>>>,
+}
+
+impl<T> ContextMenu<T> {
+    /// `make_menu` returns None when the data has no action to show
+    pub fn new(make_menu: impl Fn(&T) -> Option<Menu<Clipboard>> + 'static) -> Self {
+        Self {
+            make_menu: Box::new(make_menu),
+        }
+    }
+}
+
+impl<T, W: Widget<T>> Controller<T, W> for ContextMenu<T> {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        if let Event::MouseDown(mouse) = event {
+            if mouse.button.is_right() {
+                if let Some(menu) = (self.make_menu)(data) {
+                    ctx.show_context_menu(menu, mouse.window_pos);
+                    ctx.set_handled();
+
+                    return;
+                }
+            }
+        }
+
+        child.event(ctx, event, data, env)
+    }
+}
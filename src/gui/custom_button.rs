@@ -8,9 +8,12 @@ use druid::{
 };
 use tracing::trace;
 
+use super::clickable::{self, ClickAction};
+
 pub struct CustomButton<T> {
     child: WidgetPod<T, Box<dyn Widget<T>>>,
     style: Box<dyn StyleSheet<T>>,
+    on_click: Option<Box<ClickAction<T>>>,
 }
 
 impl<T> CustomButton<T> {
@@ -18,9 +21,17 @@ impl<T> CustomButton<T> {
         Self {
             child: WidgetPod::new(Box::new(child)),
             style: Box::new(DefaultStyle),
+            on_click: None,
         }
     }
 
+    /// `f` is called when the button is clicked, or Enter or Space is pressed on the focused one
+    pub fn with_click(mut self, f: impl Fn(&mut EventCtx, &mut T, &Env) + 'static) -> Self {
+        self.on_click = Some(Box::new(f));
+
+        self
+    }
+
     pub fn style<S: StyleSheet<T> + 'static>(mut self, style: S) -> Self {
         self.style = Box::new(style);
 
@@ -39,7 +50,7 @@ macro_rules! style_from_ctx {
         } else {
             if $ctx.is_active() {
                 $style.pressed()
-            } else if $ctx.is_hot() {
+            } else if $ctx.is_hot() || $ctx.is_focused() {
                 $style.hovered()
             } else {
                 $style.enabled()
@@ -50,33 +61,15 @@ macro_rules! style_from_ctx {
 
 impl<T: Data> Widget<T> for CustomButton<T> {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
-        match event {
-            Event::MouseDown(_) => {
-                if !ctx.is_disabled() {
-                    ctx.set_active(true);
-                    ctx.request_paint();
-                    trace!("Button {:?} pressed", ctx.widget_id());
-                }
-            }
-
-            Event::MouseUp(_) => {
-                if ctx.is_active() && !ctx.is_disabled() {
-                    ctx.request_paint();
-                    trace!("Button {:?} released", ctx.widget_id());
-                }
-                ctx.set_active(false);
-            }
-
-            _ => {}
+        if clickable::event(ctx, event, data, env, self.on_click.as_deref()) {
+            return;
         }
 
         self.child.event(ctx, event, data, env)
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
-        if let LifeCycle::HotChanged(_) | LifeCycle::DisabledChanged(_) = event {
-            ctx.request_paint();
-        }
+        clickable::lifecycle(ctx, event);
         self.child.lifecycle(ctx, event, data, env)
     }
 
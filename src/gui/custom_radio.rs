@@ -8,10 +8,13 @@ use druid::{
 };
 use tracing::trace;
 
+use super::clickable::{self, ClickAction};
+
 pub struct CustomRadio<T> {
     child: WidgetPod<T, Box<dyn Widget<T>>>,
     variant: T,
     style: Box<dyn StyleSheet<T>>,
+    on_click: Option<Box<ClickAction<T>>>,
 }
 
 impl<T> CustomRadio<T> {
@@ -20,9 +23,17 @@ impl<T> CustomRadio<T> {
             child: WidgetPod::new(Box::new(child)),
             variant,
             style: Box::new(DefaultStyle),
+            on_click: None,
         }
     }
 
+    /// `f` is called when the radio is clicked, or Enter or Space is pressed on the focused one
+    pub fn with_click(mut self, f: impl Fn(&mut EventCtx, &mut T, &Env) + 'static) -> Self {
+        self.on_click = Some(Box::new(f));
+
+        self
+    }
+
     pub fn style<S: StyleSheet<T> + 'static>(mut self, style: S) -> Self {
         self.style = Box::new(style);
 
@@ -41,7 +52,7 @@ macro_rules! style_from_ctx {
         } else {
             if $ctx.is_active() || $variant.same($data) {
                 $style.chosen()
-            } else if $ctx.is_hot() {
+            } else if $ctx.is_hot() || $ctx.is_focused() {
                 $style.hovered()
             } else {
                 $style.enabled()
@@ -52,33 +63,15 @@ macro_rules! style_from_ctx {
 
 impl<T: Data> Widget<T> for CustomRadio<T> {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
-        match event {
-            Event::MouseDown(_) => {
-                if !ctx.is_disabled() {
-                    ctx.set_active(true);
-                    ctx.request_paint();
-                    trace!("Button {:?} pressed", ctx.widget_id());
-                }
-            }
-
-            Event::MouseUp(_) => {
-                if ctx.is_active() && !ctx.is_disabled() {
-                    ctx.request_paint();
-                    trace!("Button {:?} released", ctx.widget_id());
-                }
-                ctx.set_active(false);
-            }
-
-            _ => {}
+        if clickable::event(ctx, event, data, env, self.on_click.as_deref()) {
+            return;
         }
 
         self.child.event(ctx, event, data, env)
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
-        if let LifeCycle::HotChanged(_) | LifeCycle::DisabledChanged(_) = event {
-            ctx.request_paint();
-        }
+        clickable::lifecycle(ctx, event);
         self.child.lifecycle(ctx, event, data, env)
     }
 
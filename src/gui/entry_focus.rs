@@ -0,0 +1,55 @@
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, KbKey, Widget};
+
+use super::{DELETE_ENTRY, FOCUS_FIRST_ENTRY, PICK_ENTRY};
+use crate::clipboard::Entry;
+
+/// take the focus on [`FOCUS_FIRST_ENTRY`], the command is handled by the first entry, so the
+/// later ones don't get it. the focused entry is deleted by Delete, and the digits pick the
+/// entries shown in the list by their positions
+pub struct EntryFocus;
+
+impl<W: Widget<Entry>> Controller<Entry, W> for EntryFocus {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut Entry,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(command) if command.is(FOCUS_FIRST_ENTRY) => {
+                ctx.request_focus();
+                ctx.scroll_to_view();
+                ctx.set_handled();
+            }
+
+            Event::KeyDown(key) if ctx.is_focused() && key.key == KbKey::Delete => {
+                ctx.submit_command(DELETE_ENTRY.with(data.clone()));
+                ctx.set_handled();
+            }
+
+            Event::KeyDown(key) if ctx.is_focused() => match quick_pick_index(&key.key) {
+                Some(index) => {
+                    ctx.submit_command(PICK_ENTRY.with(index));
+                    ctx.set_handled();
+                }
+                None => child.event(ctx, event, data, env),
+            },
+
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+/// 1 to 9 pick the first to the ninth entry
+fn quick_pick_index(key: &KbKey) -> Option<usize> {
+    match key {
+        KbKey::Character(c) => match c.parse::<usize>() {
+            Ok(digit @ 1..=9) => Some(digit - 1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
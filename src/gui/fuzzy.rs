@@ -0,0 +1,86 @@
+//! the fuzzy matching of the search query, the query chars must appear in the text in order
+
+use std::ops::Range;
+
+/// match the `query` against the `text` ignoring the case and the whitespaces of the query, the
+/// byte ranges of the matched chars are returned, the shortest matched part is preferred
+pub fn fuzzy_match(query: &str, text: &str) -> Option<Vec<Range<usize>>> {
+    let query = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        return Some(vec![]);
+    }
+
+    let chars = text
+        .char_indices()
+        .map(|(offset, c)| (offset, c, fold(c)))
+        .collect::<Vec<_>>();
+
+    // the end of the first match
+    let mut matched = 0;
+    let end = chars.iter().position(|&(_, _, c)| {
+        if c == query[matched] {
+            matched += 1;
+        }
+
+        matched == query.len()
+    })?;
+
+    // the latest start before the end, it makes the match shorter
+    let mut remaining = query.len();
+    let start = (0..=end)
+        .rev()
+        .find(|&index| {
+            if chars[index].2 == query[remaining - 1] {
+                remaining -= 1;
+            }
+
+            remaining == 0
+        })
+        .unwrap_or_default();
+
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let mut matched = 0;
+    for &(offset, c, folded) in &chars[start..=end] {
+        if matched == query.len() || folded != query[matched] {
+            continue;
+        }
+        matched += 1;
+
+        let range = offset..offset + c.len_utf8();
+        match ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => ranges.push(range),
+        }
+    }
+
+    Some(ranges)
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_fuzzily() {
+        assert_eq!(fuzzy_match("", "abc"), Some(vec![]));
+        assert_eq!(fuzzy_match("abc", "ab"), None);
+        assert_eq!(fuzzy_match("ba", "ab"), None);
+
+        assert_eq!(fuzzy_match("AC", "abc"), Some(vec![0..1, 2..3]));
+        assert_eq!(fuzzy_match("he wo", "Hello World"), Some(vec![0..2, 6..8]));
+        // the shorter match at the end wins over the greedy one
+        assert_eq!(
+            fuzzy_match("cgo", "cat and a cargo"),
+            Some(vec![10..11, 13..15])
+        );
+        assert_eq!(fuzzy_match("中文", "中a文"), Some(vec![0..3, 4..7]));
+    }
+}
@@ -0,0 +1,80 @@
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, KbKey, LifeCycle, LifeCycleCtx, UpdateCtx, Widget};
+
+use super::{pick, Clipboard, DELETE_ENTRY, FOCUS_FIRST_ENTRY, PICK_ENTRY};
+
+/// the keyboard actions on the whole history, Tab cycles the content types, and the entries are
+/// picked and deleted by the commands of the focused entry
+pub struct HistoryKeys;
+
+impl<W: Widget<Clipboard>> Controller<Clipboard, W> for HistoryKeys {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut Clipboard,
+        env: &Env,
+    ) {
+        match event {
+            // before the children, the search box would move the focus by Tab
+            Event::KeyDown(key) if key.key == KbKey::Tab => {
+                data.filter.content_type = data.filter.content_type.cycle(key.mods.shift());
+                ctx.set_handled();
+            }
+
+            Event::Command(command) if command.is(PICK_ENTRY) => {
+                let index = *command.get_unchecked(PICK_ENTRY);
+                let entry = data
+                    .contents
+                    .iter()
+                    .filter(|entry| data.filter.accept(entry))
+                    .nth(index);
+
+                if let Some(entry) = entry {
+                    pick(ctx, entry, env);
+                }
+                ctx.set_handled();
+            }
+
+            Event::Command(command) if command.is(DELETE_ENTRY) => {
+                data.delete(command.get_unchecked(DELETE_ENTRY), env);
+                ctx.set_handled();
+            }
+
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &Clipboard,
+        env: &Env,
+    ) {
+        // the history is shown again after unlocking
+        if let LifeCycle::WidgetAdded = event {
+            ctx.submit_command(FOCUS_FIRST_ENTRY);
+        }
+
+        child.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &Clipboard,
+        data: &Clipboard,
+        env: &Env,
+    ) {
+        // nothing can take the keys before the first entry comes
+        if old_data.contents.is_empty() && !data.contents.is_empty() && !ctx.has_focus() {
+            ctx.submit_command(FOCUS_FIRST_ENTRY);
+        }
+
+        child.update(ctx, old_data, data, env)
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use crossbeam_channel::Sender;
+use druid::widget::Controller;
+use druid::{Data, Env, UpdateCtx, Widget};
+
+use super::{Clipboard, STORAGE_SENDER};
+use crate::storage::Command;
+
+/// send the history snapshot to the storage when it is changed
+pub struct HistorySaver;
+
+impl<W: Widget<Clipboard>> Controller<Clipboard, W> for HistorySaver {
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &Clipboard,
+        data: &Clipboard,
+        env: &Env,
+    ) {
+        // the contents are cleared when locking, they must not overwrite the saved history
+        if data.lock.status.is_open() && !old_data.contents.same(&data.contents) {
+            let sender: Arc<Sender<Command>> = env.get(&STORAGE_SENDER);
+
+            // the storage may be unavailable, then the history is not saved
+            let _ = sender.send(Command::Save(data.contents.clone()));
+        }
+
+        child.update(ctx, old_data, data, env)
+    }
+}
@@ -1,61 +1,359 @@
+use std::mem;
 use std::sync::Arc;
 
 use crossbeam_channel::{Receiver, Sender};
 use custom_button::CustomButton;
 use custom_radio::CustomRadio;
 use druid::im::Vector;
-use druid::lens::Map;
-use druid::widget::{Container, Flex, Image, Label, LineBreaking, List, Svg, ViewSwitcher};
-use druid::{Color, Data, ExtEventSink, Key, Lens, Widget, WidgetExt};
+use druid::lens::{Constant, Map};
+use druid::text::{Attribute, RichText, RichTextBuilder};
+use druid::widget::{
+    Container, CrossAxisAlignment, Either, EnvScope, Flex, Image, Label, LineBreaking, List,
+    SizedBox, Svg, TextBox, ViewSwitcher,
+};
+use druid::{
+    ArcStr, Color, Data, Env, EventCtx, ExtEventSink, FontStyle, FontWeight, Key, Lens, LensExt,
+    Menu, MenuItem, Point, Selector, Target, Widget, WidgetExt,
+};
 
-use crate::clipboard::Content;
+use crate::clipboard::{
+    self, Content, ContentFiles, ContentHtml, Entry, FileOperation, Removed, Selection,
+};
+use crate::config::{Config, ThemeConfig};
+use crate::gui::context_menu::ContextMenu;
+use crate::gui::entry_focus::EntryFocus;
+use crate::gui::fuzzy::fuzzy_match;
+use crate::gui::history_keys::HistoryKeys;
+use crate::gui::history_saver::HistorySaver;
 use crate::gui::list_filter::ListFilter;
+use crate::gui::passphrase_input::PassphraseInput;
+use crate::gui::search_input::SearchInput;
+use crate::gui::style::{ACCENT_COLOR, BACKGROUND_COLOR, TEXT_COLOR};
+use crate::gui::window_raiser::WindowRaiser;
+use crate::ipc;
+use crate::storage::{self, Prompt};
 
 mod assets;
+mod clickable;
+mod context_menu;
 mod custom_button;
 mod custom_radio;
+mod entry_focus;
+mod fuzzy;
+mod history_keys;
+mod history_saver;
 mod list_filter;
+mod passphrase_input;
+mod search_input;
 mod style;
+mod window_raiser;
 
-pub const CONTENT_SENDER: Key<Arc<Sender<Content>>> = Key::new("history_clipboard.content_sender");
+pub const CONTENT_SENDER: Key<Arc<Sender<Entry>>> = Key::new("history_clipboard.content_sender");
+pub const STORAGE_SENDER: Key<Arc<Sender<storage::Command>>> =
+    Key::new("history_clipboard.storage_sender");
+pub const REMOVED_SENDER: Key<Arc<Sender<Removed>>> = Key::new("history_clipboard.removed_sender");
+/// the entries pinned or unpinned
+pub const PINNED_SENDER: Key<Arc<Sender<Entry>>> = Key::new("history_clipboard.pinned_sender");
+/// raise the window, it is asked by another launch
+pub const SHOW_WINDOW: Selector = Selector::new("history_clipboard.show_window");
+/// show the window near the pointer, it is hidden again after picking an entry
+pub const POPUP: Selector<Point> = Selector::new("history_clipboard.popup");
+const ENTRY_PICKED: Selector = Selector::new("history_clipboard.entry_picked");
+const FOCUS_FIRST_ENTRY: Selector = Selector::new("history_clipboard.focus_first_entry");
+/// pick the entry at the index of the shown ones
+const PICK_ENTRY: Selector<usize> = Selector::new("history_clipboard.pick_entry");
+const DELETE_ENTRY: Selector<Entry> = Selector::new("history_clipboard.delete_entry");
+/// the search query, the entry labels highlight the matched parts
+const SEARCH_QUERY: Key<ArcStr> = Key::new("history_clipboard.search_query");
 
 #[derive(Debug, Clone, Eq, PartialEq, Data, Copy)]
 enum ContentType {
     All,
     Text,
     Image,
+    Pinned,
+}
+
+impl ContentType {
+    /// the next type in the radio order, or the previous one when `backward`
+    fn cycle(self, backward: bool) -> Self {
+        match (self, backward) {
+            (ContentType::All, false) | (ContentType::Image, true) => ContentType::Text,
+            (ContentType::Text, false) | (ContentType::Pinned, true) => ContentType::Image,
+            (ContentType::Image, false) | (ContentType::All, true) => ContentType::Pinned,
+            (ContentType::Pinned, false) | (ContentType::Text, true) => ContentType::All,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Data, Lens)]
+struct Filter {
+    content_type: ContentType,
+    /// None means accept any selection
+    selection: Option<Selection>,
+    /// only the texts fuzzy matched by the non-empty query are accepted
+    query: String,
+}
+
+impl Filter {
+    fn accept(&self, entry: &Entry) -> bool {
+        let content_type_accepted = match self.content_type {
+            ContentType::All => true,
+            ContentType::Text => matches!(entry.content, Content::Text(_) | Content::Html(_)),
+            ContentType::Image => matches!(entry.content, Content::Image(_)),
+            ContentType::Pinned => entry.pinned,
+        };
+
+        let query_accepted = self.query.trim().is_empty()
+            || match &entry.content {
+                Content::Text(text) => fuzzy_match(&self.query, text).is_some(),
+                Content::Html(html) => fuzzy_match(&self.query, &html.text).is_some(),
+                Content::Files(_) | Content::Image(_) => false,
+            };
+
+        content_type_accepted
+            && query_accepted
+            && self
+                .selection
+                .map_or(true, |selection| selection == entry.selection)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+enum LockStatus {
+    /// the history is not persisted, so there is nothing to lock
+    Disabled,
+    /// waiting for the storage
+    Opening,
+    Locked(Prompt),
+    Unlocked,
+}
+
+impl LockStatus {
+    fn is_open(&self) -> bool {
+        matches!(self, LockStatus::Disabled | LockStatus::Unlocked)
+    }
+}
+
+#[derive(Debug, Clone, Data, Lens)]
+struct Lock {
+    status: LockStatus,
+    error: Option<Arc<str>>,
+    passphrase: String,
 }
 
 #[derive(Debug, Clone, Data, Lens)]
 pub struct Clipboard {
     max_size: usize,
-    content_type: ContentType,
-    contents: Vector<Content>,
+    filter: Filter,
+    contents: Vector<Entry>,
+    lock: Lock,
+    theme: Arc<ThemeConfig>,
+    /// the clear button is asking for the confirmation
+    confirming_clear: bool,
 }
 
 impl Clipboard {
-    pub fn new(max_size: usize) -> Self {
+    /// the history is shown after the storage is unlocked when it is `persisted`
+    pub fn new(config: &Config, persisted: bool) -> Self {
         Self {
-            max_size,
-            content_type: ContentType::All,
+            max_size: config.history.max_size,
+            filter: Filter {
+                content_type: ContentType::All,
+                selection: None,
+                query: String::new(),
+            },
             contents: Vector::new(),
+            lock: Lock {
+                status: if persisted {
+                    LockStatus::Opening
+                } else {
+                    LockStatus::Disabled
+                },
+                error: None,
+                passphrase: String::new(),
+            },
+            theme: Arc::new(config.theme.clone()),
+            confirming_clear: false,
         }
     }
+
+    /// drop the oldest unpinned entries beyond `max_size`
+    fn truncate(&mut self) {
+        clipboard::truncate_history(&mut self.contents, self.max_size);
+    }
+
+    /// add the captured or restored `entry` to the front
+    fn push(&mut self, entry: Entry) {
+        clipboard::push_history(&mut self.contents, entry, self.max_size);
+    }
+
+    /// add the saved `entries` after the ones captured while locked
+    fn merge(&mut self, entries: Vec<Entry>) {
+        clipboard::merge_history(&mut self.contents, entries, self.max_size);
+    }
+
+    /// pin or unpin the `changed` entry, the unpinned one counts for the history size again
+    fn set_pinned(&mut self, changed: &Entry, pinned: bool, env: &Env) {
+        if let Some(index) = self.contents.iter().position(|entry| entry.same(changed)) {
+            self.contents[index].pinned = pinned;
+            let entry = self.contents[index].clone();
+            self.truncate();
+
+            let sender: Arc<Sender<Entry>> = env.get(&PINNED_SENDER);
+            let _ = sender.send(entry);
+        }
+    }
+
+    /// delete the `deleted` entry, the clipboard forgets its content
+    fn delete(&mut self, deleted: &Entry, env: &Env) {
+        if let Some(index) = self.contents.iter().position(|entry| entry.same(deleted)) {
+            let entry = self.contents.remove(index);
+
+            let sender: Arc<Sender<Removed>> = env.get(&REMOVED_SENDER);
+            let _ = sender.send(Removed::Entry(entry));
+        }
+    }
+
+    fn clear(&mut self, env: &Env) {
+        self.contents.clear();
+        self.confirming_clear = false;
+
+        let sender: Arc<Sender<Removed>> = env.get(&REMOVED_SENDER);
+        let _ = sender.send(Removed::All);
+    }
 }
 
 pub fn new_ui() -> impl Widget<Clipboard> {
-    const BACKGROUND_COLOR: Color = Color::rgb8(242, 242, 242);
+    let ui = ViewSwitcher::new(
+        |clipboard: &Clipboard, _env| clipboard.lock.status.is_open(),
+        |is_open, _clipboard, _env| {
+            if *is_open {
+                make_history_ui().boxed()
+            } else {
+                make_lock_ui().lens(Clipboard::lock).boxed()
+            }
+        },
+    )
+    .background(BACKGROUND_COLOR)
+    .controller(HistorySaver)
+    .controller(WindowRaiser::default());
 
+    // the theme is in the data, so the reloaded one restyles the widgets
+    EnvScope::new(
+        |env, clipboard: &Clipboard| style::set_theme(env, &clipboard.theme),
+        ui,
+    )
+}
+
+fn make_history_ui() -> impl Widget<Clipboard> {
     let list = make_list();
 
     let top = make_top_ui();
 
+    let search_box = make_search_box();
+
+    let selection_bar = make_selection_bar();
+
     Flex::column()
         .with_flex_child(top, 0.1)
+        .with_child(search_box)
+        .with_child(selection_bar)
         .with_flex_child(list, 0.9)
         .expand_height()
         .expand_height()
-        .background(BACKGROUND_COLOR)
+        .controller(HistoryKeys)
+}
+
+fn make_lock_ui() -> impl Widget<Lock> {
+    const ERROR_COLOR: Color = Color::rgb8(200, 40, 40);
+
+    let message = Label::dynamic(|lock: &Lock, _env| {
+        match lock.status {
+            LockStatus::Locked(Prompt::NewPassphrase) => "Set a passphrase to encrypt the history",
+            LockStatus::Locked(Prompt::Passphrase) => "Enter the passphrase to unlock the history",
+            LockStatus::Locked(Prompt::Keyring) => "Unlock the keyring to open the history",
+            _ => "Opening the history...",
+        }
+        .to_string()
+    })
+    .with_text_size(16.0)
+    .with_text_color(TEXT_COLOR)
+    .with_line_break_mode(LineBreaking::WordWrap);
+
+    let input = Either::new(
+        |lock: &Lock, _env| {
+            matches!(
+                lock.status,
+                LockStatus::Locked(Prompt::NewPassphrase | Prompt::Passphrase)
+            )
+        },
+        Label::dynamic(|lock: &Lock, _env| {
+            if lock.passphrase.is_empty() {
+                "Click and type the passphrase".to_string()
+            } else {
+                "\u{2022}".repeat(lock.passphrase.chars().count())
+            }
+        })
+        .with_text_size(16.0)
+        .with_text_color(TEXT_COLOR)
+        .padding(5.0)
+        .border(Color::grey8(180), 1.0)
+        .fix_width(250.0)
+        .controller(PassphraseInput),
+        SizedBox::empty(),
+    );
+
+    let button = Either::new(
+        |lock: &Lock, _env| matches!(lock.status, LockStatus::Locked(_)),
+        CustomButton::new(
+            Label::dynamic(|lock: &Lock, _env| {
+                match lock.status {
+                    LockStatus::Locked(Prompt::Keyring) => "Retry",
+                    _ => "Unlock",
+                }
+                .to_string()
+            })
+            .with_text_size(14.0)
+            .with_text_color(TEXT_COLOR)
+            .padding(5.0),
+        )
+        .style(style::button::CustomStyleSheet)
+        .with_click(|_ctx, lock: &mut Lock, env| unlock(lock, env)),
+        SizedBox::empty(),
+    );
+
+    let error =
+        Label::dynamic(|lock: &Lock, _env| lock.error.as_deref().unwrap_or_default().to_string())
+            .with_text_size(14.0)
+            .with_text_color(ERROR_COLOR)
+            .with_line_break_mode(LineBreaking::WordWrap);
+
+    Flex::column()
+        .with_child(message)
+        .with_spacer(10.0)
+        .with_child(input)
+        .with_spacer(10.0)
+        .with_child(button)
+        .with_spacer(10.0)
+        .with_child(error)
+        .padding(20.0)
+        .center()
+}
+
+/// ask the storage to unlock, the keyring needs no passphrase
+fn unlock(lock: &mut Lock, env: &Env) {
+    let passphrase = match lock.status {
+        LockStatus::Locked(Prompt::Keyring) => String::new(),
+        LockStatus::Locked(_) if !lock.passphrase.is_empty() => mem::take(&mut lock.passphrase),
+        _ => return,
+    };
+
+    lock.status = LockStatus::Opening;
+    lock.error = None;
+
+    let sender: Arc<Sender<storage::Command>> = env.get(&STORAGE_SENDER);
+    let _ = sender.send(storage::Command::Unlock(passphrase));
 }
 
 fn make_top_ui() -> Flex<Clipboard> {
@@ -64,76 +362,239 @@ fn make_top_ui() -> Flex<Clipboard> {
         ContentType::All,
     )
     .style(style::radio::CustomStyleSheet)
-    .on_click(|_ctx, content_type: &mut ContentType, _env| {
+    .with_click(|_ctx, content_type: &mut ContentType, _env| {
         *content_type = ContentType::All;
     })
     .expand_width()
     .expand_height()
-    .lens(Clipboard::content_type);
+    .lens(Clipboard::filter.then(Filter::content_type));
 
     let text_radio = CustomRadio::new(
         Svg::new(assets::TEXT_SVG.parse().unwrap()).center(),
         ContentType::Text,
     )
     .style(style::radio::CustomStyleSheet)
-    .on_click(|_ctx, content_type: &mut ContentType, _env| {
+    .with_click(|_ctx, content_type: &mut ContentType, _env| {
         *content_type = ContentType::Text;
     })
     .expand_width()
     .expand_height()
-    .lens(Clipboard::content_type);
+    .lens(Clipboard::filter.then(Filter::content_type));
 
     let image_radio = CustomRadio::new(
         Svg::new(assets::IMAGE_SVG.parse().unwrap()).center(),
         ContentType::Image,
     )
     .style(style::radio::CustomStyleSheet)
-    .on_click(|_ctx, content_type: &mut ContentType, _env| {
+    .with_click(|_ctx, content_type: &mut ContentType, _env| {
         *content_type = ContentType::Image;
     })
     .expand_width()
     .expand_height()
-    .lens(Clipboard::content_type);
+    .lens(Clipboard::filter.then(Filter::content_type));
+
+    let pinned_radio = CustomRadio::new(
+        Svg::new(assets::PIN_SVG.parse().unwrap()).center(),
+        ContentType::Pinned,
+    )
+    .style(style::radio::CustomStyleSheet)
+    .with_click(|_ctx, content_type: &mut ContentType, _env| {
+        *content_type = ContentType::Pinned;
+    })
+    .expand_width()
+    .expand_height()
+    .lens(Clipboard::filter.then(Filter::content_type));
 
     Flex::row()
-        .with_flex_child(all_radio.padding(10.0), 0.3)
-        .with_flex_child(text_radio.padding(10.0), 0.3)
-        .with_flex_child(image_radio.padding(10.0), 0.3)
+        .with_flex_child(all_radio.padding(10.0), 0.25)
+        .with_flex_child(text_radio.padding(10.0), 0.25)
+        .with_flex_child(image_radio.padding(10.0), 0.25)
+        .with_flex_child(pinned_radio.padding(10.0), 0.25)
 }
 
-fn make_list() -> impl Widget<Clipboard> {
-    const TEXT_COLOR: Color = Color::BLACK;
+fn make_search_box() -> impl Widget<Clipboard> {
+    TextBox::new()
+        .with_placeholder("Search")
+        .with_text_size(16.0)
+        .controller(SearchInput)
+        .expand_width()
+        .lens(Clipboard::filter.then(Filter::query))
+        .padding((10.0, 0.0))
+}
+
+fn make_selection_bar() -> impl Widget<Clipboard> {
+    let selection_radio = |name: &'static str, variant: Option<Selection>| {
+        let label = Label::new(name)
+            .with_text_size(14.0)
+            .with_text_color(TEXT_COLOR)
+            .center()
+            .padding(5.0);
+
+        CustomRadio::new(label, variant)
+            .style(style::radio::CustomStyleSheet)
+            .with_click(move |_ctx, selection: &mut Option<Selection>, _env| {
+                *selection = variant;
+            })
+            .expand_width()
+            .lens(Clipboard::filter.then(Filter::selection))
+    };
+
+    Flex::row()
+        .with_flex_child(selection_radio("Any", None).padding(5.0), 0.25)
+        .with_flex_child(
+            selection_radio("Clipboard", Some(Selection::Clipboard)).padding(5.0),
+            0.25,
+        )
+        .with_flex_child(
+            selection_radio("Primary", Some(Selection::Primary)).padding(5.0),
+            0.25,
+        )
+        .with_flex_child(
+            selection_radio("Secondary", Some(Selection::Secondary)).padding(5.0),
+            0.25,
+        )
+        .with_child(make_clear_button().padding(5.0))
+        .with_child(make_lock_button().padding(5.0))
+        .padding((5.0, 0.0))
+}
+
+/// clear the history after the confirmation
+fn make_clear_button() -> impl Widget<Clipboard> {
+    let text_button = |text: &str| {
+        CustomButton::new(
+            Label::new(text)
+                .with_text_size(14.0)
+                .with_text_color(TEXT_COLOR)
+                .center()
+                .padding(5.0),
+        )
+        .style(style::button::CustomStyleSheet)
+    };
 
+    let clear_button =
+        text_button("Clear all").with_click(|_ctx, clipboard: &mut Clipboard, _env| {
+            clipboard.confirming_clear = true;
+        });
+
+    let confirmation = Flex::row()
+        .with_child(
+            Label::new("Clear all entries?")
+                .with_text_size(14.0)
+                .with_text_color(TEXT_COLOR),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            text_button("Clear").with_click(|_ctx, clipboard: &mut Clipboard, env| {
+                clipboard.clear(env);
+            }),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            text_button("Cancel").with_click(|_ctx, clipboard: &mut Clipboard, _env| {
+                clipboard.confirming_clear = false;
+            }),
+        );
+
+    Either::new(
+        |clipboard: &Clipboard, _env| clipboard.confirming_clear,
+        confirmation,
+        clear_button,
+    )
+}
+
+/// lock the history, it is hidden until unlocking again
+fn make_lock_button() -> impl Widget<Clipboard> {
+    let button = CustomButton::new(
+        Label::new("Lock")
+            .with_text_size(14.0)
+            .with_text_color(TEXT_COLOR)
+            .center()
+            .padding(5.0),
+    )
+    .style(style::button::CustomStyleSheet)
+    .with_click(|_ctx, clipboard: &mut Clipboard, env| {
+        let sender: Arc<Sender<storage::Command>> = env.get(&STORAGE_SENDER);
+        let _ = sender.send(storage::Command::Lock);
+
+        clipboard.lock.status = LockStatus::Opening;
+        clipboard.contents.clear();
+    });
+
+    Either::new(
+        |clipboard: &Clipboard, _env| clipboard.lock.status == LockStatus::Unlocked,
+        button,
+        SizedBox::empty(),
+    )
+}
+
+fn make_list() -> impl Widget<Clipboard> {
     let list = List::new(|| {
-        let clickable_label = ViewSwitcher::new(
-            |content: &Content, _env| content.clone(),
-            |content, _clipboard_content: &Content, _env| match content {
+        let preview = ViewSwitcher::new(
+            |entry: &Entry, env| {
+                // only the texts are highlighted, the others needn't rebuild on searching
+                let query = match entry.content {
+                    Content::Text(_) | Content::Html(_) => env.get(&SEARCH_QUERY),
+                    Content::Files(_) | Content::Image(_) => ArcStr::from(""),
+                };
+
+                (entry.content.clone(), query)
+            },
+            |(content, query), _entry: &Entry, _env| match content {
                 Content::Text(text) => {
-                    let label = Label::new(text.to_string())
+                    let label = Label::raw()
                         .with_text_size(20.0)
                         .with_line_break_mode(LineBreaking::Clip)
                         .with_text_color(TEXT_COLOR)
+                        .lens(Constant(highlight(
+                            RichText::new(text.clone()),
+                            text,
+                            query,
+                        )))
                         .padding(5.0);
 
-                    CustomButton::new(label)
-                        .style(style::button::CustomStyleSheet)
-                        .boxed()
+                    label.boxed()
                 }
 
+                Content::Html(html) => {
+                    let label = Label::raw()
+                        .with_text_size(20.0)
+                        .with_line_break_mode(LineBreaking::Clip)
+                        .with_text_color(TEXT_COLOR)
+                        .lens(Constant(rich_text(html, query)))
+                        .padding(5.0);
+
+                    label.boxed()
+                }
+
+                Content::Files(files) => make_files_preview(files).boxed(),
+
                 Content::Image(content_img) => {
                     let image = Image::new(content_img.image_buf.clone()).padding(5.0);
 
-                    CustomButton::new(image)
-                        .style(style::button::CustomStyleSheet)
-                        .boxed()
+                    image.boxed()
                 }
             },
-        )
-        .on_click(|_ctx, content: &mut Content, env| {
-            let sender: Arc<Sender<Content>> = env.get(&CONTENT_SENDER);
+        );
 
-            let _ = sender.send(content.clone());
-        });
+        let pin_mark = Either::new(
+            |entry: &Entry, _env| entry.pinned,
+            Svg::new(assets::PIN_SVG.parse().unwrap())
+                .fix_size(16.0, 16.0)
+                .padding(5.0),
+            SizedBox::empty(),
+        );
+
+        let preview = Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_flex_child(preview, 1.0)
+            .with_child(pin_mark);
+
+        // the button outlives the preview, so the focus stays at the position after deleting
+        let clickable_label = CustomButton::new(preview)
+            .style(style::button::CustomStyleSheet)
+            .with_click(|ctx, entry: &mut Entry, env| pick(ctx, entry, env))
+            .controller(EntryFocus)
+            .controller(ContextMenu::new(make_entry_menu));
 
         Container::new(clickable_label)
             .expand_width()
@@ -141,36 +602,272 @@ fn make_list() -> impl Widget<Clipboard> {
             .padding(10.0)
     });
 
-    let list =
-        ListFilter::new(
-            list,
-            |content: &Content, content_type: &ContentType| match content_type {
-                ContentType::All => true,
-                ContentType::Text => matches!(content, Content::Text(_)),
-                ContentType::Image => matches!(content, Content::Image(_)),
-            },
-        );
+    let list = ListFilter::new(list, |entry: &Entry, filter: &Filter| filter.accept(entry));
 
-    list.center()
+    let list = list
+        .center()
         .expand_width()
         .scroll()
         .vertical()
         .lens(Map::new(
-            |clipboard: &Clipboard| (clipboard.contents.clone(), clipboard.content_type),
-            |clipboard, (contents, content_type)| {
+            |clipboard: &Clipboard| (clipboard.contents.clone(), clipboard.filter.clone()),
+            |clipboard, (contents, filter)| {
                 clipboard.contents = contents;
-                clipboard.content_type = content_type;
+                clipboard.filter = filter;
             },
-        ))
+        ));
+
+    EnvScope::new(
+        |env, clipboard: &Clipboard| {
+            env.set(SEARCH_QUERY, ArcStr::from(clipboard.filter.query.as_str()))
+        },
+        list,
+    )
 }
 
-pub fn update_clipboard(event_sink: ExtEventSink, content_receiver: Receiver<Content>) {
-    for content in content_receiver {
+/// restore the `entry` to the clipboard
+fn pick(ctx: &mut EventCtx, entry: &Entry, env: &Env) {
+    let sender: Arc<Sender<Entry>> = env.get(&CONTENT_SENDER);
+
+    let _ = sender.send(entry.clone());
+    ctx.submit_command(ENTRY_PICKED);
+}
+
+/// list the first files with their icons
+fn make_files_preview(files: &ContentFiles) -> impl Widget<Entry> {
+    const MAX_FILES: usize = 2;
+
+    let mut column = Flex::column().cross_axis_alignment(CrossAxisAlignment::Start);
+
+    for file in files.files.iter().take(MAX_FILES) {
+        let icon = if file.is_dir {
+            assets::FOLDER_SVG
+        } else {
+            assets::FILE_SVG
+        };
+
+        let row = Flex::row()
+            .with_child(Svg::new(icon.parse().unwrap()).fix_size(20.0, 20.0))
+            .with_spacer(5.0)
+            .with_flex_child(
+                Label::new(file.name.to_string())
+                    .with_text_size(18.0)
+                    .with_line_break_mode(LineBreaking::Clip)
+                    .with_text_color(TEXT_COLOR),
+                1.0,
+            );
+
+        column.add_child(row);
+    }
+
+    let mut summary = vec![];
+    if files.files.len() > MAX_FILES {
+        summary.push(format!("and {} more", files.files.len() - MAX_FILES));
+    }
+    if files.operation == FileOperation::Cut {
+        summary.push("cut".to_string());
+    }
+    if !summary.is_empty() {
+        column.add_child(
+            Label::new(summary.join(", "))
+                .with_text_size(14.0)
+                .with_text_color(Color::grey8(120)),
+        );
+    }
+
+    column.padding(5.0)
+}
+
+/// build the formatted preview of the html, the parts matched by the search `query` are
+/// highlighted
+fn rich_text(html: &ContentHtml, query: &str) -> RichText {
+    let spans = html.spans();
+    let mut builder = RichTextBuilder::new();
+
+    for span in &spans {
+        let mut attrs = builder.push(&span.text);
+
+        if span.bold {
+            attrs.weight(FontWeight::BOLD);
+        }
+        if span.italic {
+            attrs.style(FontStyle::Italic);
+        }
+        if span.underline {
+            attrs.underline(true);
+        }
+        if span.heading {
+            attrs.size(24.0).weight(FontWeight::BOLD);
+        }
+    }
+
+    let text = spans
+        .iter()
+        .map(|span| span.text.as_str())
+        .collect::<String>();
+
+    highlight(builder.build(), &text, query)
+}
+
+/// highlight the parts of the `text` of the `rich_text` matched by the search `query`
+fn highlight(mut rich_text: RichText, text: &str, query: &str) -> RichText {
+    for range in fuzzy_match(query, text).unwrap_or_default() {
+        rich_text.add_attribute(range.clone(), Attribute::text_color(ACCENT_COLOR));
+        rich_text.add_attribute(range, Attribute::weight(FontWeight::BOLD));
+    }
+
+    rich_text
+}
+
+fn make_entry_menu(entry: &Entry) -> Option<Menu<Clipboard>> {
+    let mut menu = Menu::empty();
+
+    if let (Content::Html(_), Some(plain)) = (&entry.content, entry.to_plain_text()) {
+        menu = menu.entry(MenuItem::new("Paste as plain text").on_activate(
+            move |ctx, _clipboard: &mut Clipboard, env| {
+                let sender: Arc<Sender<Entry>> = env.get(&CONTENT_SENDER);
+
+                let _ = sender.send(plain.clone());
+                ctx.submit_command(ENTRY_PICKED);
+            },
+        ));
+    }
+
+    let changed = entry.clone();
+    let menu = menu.entry(
+        MenuItem::new(if entry.pinned { "Unpin" } else { "Pin" }).on_activate(
+            move |_ctx, clipboard: &mut Clipboard, env| {
+                clipboard.set_pinned(&changed, !changed.pinned, env);
+            },
+        ),
+    );
+
+    let deleted = entry.clone();
+    let menu = menu.entry(MenuItem::new("Delete").on_activate(
+        move |_ctx, clipboard: &mut Clipboard, env| {
+            clipboard.delete(&deleted, env);
+        },
+    ));
+
+    Some(menu)
+}
+
+pub fn update_clipboard(event_sink: ExtEventSink, content_receiver: Receiver<Entry>) {
+    for entry in content_receiver {
+        event_sink.add_idle_callback(move |clipboard: &mut Clipboard| clipboard.push(entry))
+    }
+}
+
+/// pop up the window at the pointer positions of the hotkey presses
+pub fn popup(event_sink: ExtEventSink, presses: Receiver<(f64, f64)>) {
+    for (x, y) in presses {
+        if event_sink
+            .submit_command(POPUP, Point::new(x, y), Target::Auto)
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// handle the ipc calls on the history, the entries are hidden when the history is locked
+pub fn handle_calls(
+    event_sink: ExtEventSink,
+    call_receiver: Receiver<ipc::Call>,
+    restore_sender: Sender<Entry>,
+    removed_sender: Sender<Removed>,
+) {
+    for call in call_receiver {
+        // the window is raised even when the history is locked
+        if matches!(call.request, ipc::Request::Show) {
+            let reply = match event_sink.submit_command(SHOW_WINDOW, (), Target::Auto) {
+                Ok(()) => (ipc::Response::Done, vec![]),
+                Err(_) => (
+                    ipc::Response::Error {
+                        message: "the window is closed".to_string(),
+                    },
+                    vec![],
+                ),
+            };
+            let _ = call.reply_sender.send(reply);
+
+            continue;
+        }
+
+        let restore_sender = restore_sender.clone();
+        let removed_sender = removed_sender.clone();
+
+        event_sink.add_idle_callback(move |clipboard: &mut Clipboard| {
+            let reply = if clipboard.lock.status.is_open() {
+                ipc::handle(
+                    call.request,
+                    call.data,
+                    &mut clipboard.contents,
+                    clipboard.max_size,
+                    &restore_sender,
+                    &removed_sender,
+                )
+            } else {
+                (
+                    ipc::Response::Error {
+                        message: "the history is locked".to_string(),
+                    },
+                    vec![],
+                )
+            };
+
+            let _ = call.reply_sender.send(reply);
+        })
+    }
+}
+
+/// apply the reloaded configs
+pub fn update_config(event_sink: ExtEventSink, config_receiver: Receiver<Config>) {
+    for config in config_receiver {
+        event_sink.add_idle_callback(move |clipboard: &mut Clipboard| {
+            clipboard.max_size = config.history.max_size;
+            clipboard.truncate();
+
+            if *clipboard.theme != config.theme {
+                clipboard.theme = Arc::new(config.theme);
+            }
+        })
+    }
+}
+
+/// show the history of the daemon, it replaces the contents
+pub fn update_history(event_sink: ExtEventSink, history_receiver: Receiver<ipc::History>) {
+    for history in history_receiver {
         event_sink.add_idle_callback(move |clipboard: &mut Clipboard| {
-            clipboard.contents.push_front(content);
+            let (status, error) = match history.status {
+                ipc::HistoryStatus::Disabled => (LockStatus::Disabled, None),
+                ipc::HistoryStatus::Opening => (LockStatus::Opening, None),
+                ipc::HistoryStatus::Locked { prompt, error } => (LockStatus::Locked(prompt), error),
+                ipc::HistoryStatus::Unlocked => (LockStatus::Unlocked, None),
+            };
+
+            clipboard.lock.status = status;
+            clipboard.lock.error = error.map(Into::into);
+            clipboard.contents = history.entries.into();
+            clipboard.truncate();
+        })
+    }
+}
+
+pub fn update_lock(event_sink: ExtEventSink, event_receiver: Receiver<storage::Event>) {
+    for event in event_receiver {
+        event_sink.add_idle_callback(move |clipboard: &mut Clipboard| match event {
+            storage::Event::Locked { prompt, error } => {
+                clipboard.lock.status = LockStatus::Locked(prompt);
+                clipboard.lock.error = error.map(Into::into);
+            }
+
+            storage::Event::Unlocked(entries) => {
+                clipboard.lock.status = LockStatus::Unlocked;
+                clipboard.lock.error = None;
 
-            while clipboard.contents.len() >= clipboard.max_size {
-                clipboard.contents.pop_back();
+                // the contents captured while locked are newer than the saved ones
+                clipboard.merge(entries);
             }
         })
     }
@@ -0,0 +1,56 @@
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, KbKey, LifeCycle, LifeCycleCtx, Widget};
+
+use super::Lock;
+
+/// type the passphrase into the lock, the child shows it masked, and Enter unlocks
+pub struct PassphraseInput;
+
+impl<W: Widget<Lock>> Controller<Lock, W> for PassphraseInput {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut Lock,
+        env: &Env,
+    ) {
+        match event {
+            Event::MouseDown(_) => ctx.request_focus(),
+
+            Event::KeyDown(key) if ctx.is_focused() && !key.mods.ctrl() => {
+                match &key.key {
+                    KbKey::Character(text) => data.passphrase.push_str(text),
+                    KbKey::Backspace => {
+                        data.passphrase.pop();
+                    }
+                    KbKey::Enter => super::unlock(data, env),
+                    _ => return child.event(ctx, event, data, env),
+                }
+
+                ctx.set_handled();
+
+                return;
+            }
+
+            _ => {}
+        }
+
+        child.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &Lock,
+        env: &Env,
+    ) {
+        if let LifeCycle::BuildFocusChain = event {
+            ctx.register_for_focus();
+        }
+
+        child.lifecycle(ctx, event, data, env)
+    }
+}
@@ -0,0 +1,27 @@
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, KbKey, Widget};
+
+use super::FOCUS_FIRST_ENTRY;
+
+/// leave the search box to the first matched entry by the down arrow or Enter
+pub struct SearchInput;
+
+impl<W: Widget<String>> Controller<String, W> for SearchInput {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut String,
+        env: &Env,
+    ) {
+        match event {
+            Event::KeyDown(key) if matches!(key.key, KbKey::ArrowDown | KbKey::Enter) => {
+                ctx.submit_command(FOCUS_FIRST_ENTRY);
+                ctx.set_handled();
+            }
+
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
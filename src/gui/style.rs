@@ -1,19 +1,39 @@
+use druid::{Color, Env, Key};
+
+use crate::config::{Rgba, ThemeConfig};
+
+pub const BACKGROUND_COLOR: Key<Color> = Key::new("history_clipboard.background_color");
+pub const BUTTON_COLOR: Key<Color> = Key::new("history_clipboard.button_color");
+pub const ACCENT_COLOR: Key<Color> = Key::new("history_clipboard.accent_color");
+pub const TEXT_COLOR: Key<Color> = Key::new("history_clipboard.text_color");
+
+/// set the theme colors to the `env`
+pub fn set_theme(env: &mut Env, theme: &ThemeConfig) {
+    let color = |Rgba(r, g, b, a): Rgba| Color::rgba8(r, g, b, a);
+
+    env.set(BACKGROUND_COLOR, color(theme.background));
+    env.set(BUTTON_COLOR, color(theme.button));
+    env.set(ACCENT_COLOR, color(theme.accent));
+    env.set(TEXT_COLOR, color(theme.text));
+}
+
 pub mod button {
-    use druid::Color;
+    use druid::{Color, Key};
 
     use super::super::custom_button::{BorderStyle, DefaultStyle, Style, StyleSheet};
+    use super::{ACCENT_COLOR, BUTTON_COLOR};
 
     #[derive(Debug, Default, Copy, Clone)]
     pub struct CustomStyleSheet;
 
     impl CustomStyleSheet {
         const RADIUS: f64 = 10.0;
-        fn background() -> Color {
-            Color::rgb8(251, 251, 251)
+        fn background() -> Key<Color> {
+            BUTTON_COLOR
         }
 
-        fn border() -> Color {
-            Color::rgb8(46, 179, 152)
+        fn border() -> Key<Color> {
+            ACCENT_COLOR
         }
     }
 
@@ -57,21 +77,22 @@ pub mod button {
 }
 
 pub mod radio {
-    use druid::Color;
+    use druid::{Color, Key};
 
     use super::super::custom_radio::{BorderStyle, DefaultStyle, Style, StyleSheet};
+    use super::{ACCENT_COLOR, BUTTON_COLOR};
 
     #[derive(Debug, Default, Copy, Clone)]
     pub struct CustomStyleSheet;
 
     impl CustomStyleSheet {
         const RADIUS: f64 = 10.0;
-        fn background() -> Color {
-            Color::rgb8(251, 251, 251)
+        fn background() -> Key<Color> {
+            BUTTON_COLOR
         }
 
-        fn border() -> Color {
-            Color::rgb8(46, 179, 152)
+        fn border() -> Key<Color> {
+            ACCENT_COLOR
         }
     }
 
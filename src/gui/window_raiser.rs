@@ -0,0 +1,79 @@
+use std::mem;
+
+use druid::widget::Controller;
+use druid::{Env, Event, EventCtx, KbKey, Point, Rect, Screen, Size, Target, Widget, WindowState};
+
+use super::{Clipboard, ENTRY_PICKED, FOCUS_FIRST_ENTRY, POPUP, SHOW_WINDOW};
+
+/// raise the window on [`SHOW_WINDOW`], and pop it up near the pointer on [`POPUP`], the popped
+/// up window is hidden again after picking an entry or pressing Escape
+#[derive(Default)]
+pub struct WindowRaiser {
+    popup: bool,
+}
+
+impl<W: Widget<Clipboard>> Controller<Clipboard, W> for WindowRaiser {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut Clipboard,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(command) if command.is(SHOW_WINDOW) => {
+                raise(ctx);
+                ctx.set_handled();
+            }
+
+            Event::Command(command) if command.is(POPUP) => {
+                let pointer = *command.get_unchecked(POPUP);
+                let position = popup_position(pointer, ctx.window().get_size());
+
+                ctx.window().set_position(position);
+                ctx.window().show();
+                raise(ctx);
+                self.popup = true;
+
+                ctx.submit_command(FOCUS_FIRST_ENTRY.to(Target::Window(ctx.window_id())));
+                ctx.set_handled();
+            }
+
+            Event::Command(command) if command.is(ENTRY_PICKED) => {
+                if mem::take(&mut self.popup) {
+                    ctx.window().hide();
+                }
+            }
+
+            Event::KeyDown(key) if key.key == KbKey::Escape && self.popup => {
+                self.popup = false;
+                ctx.window().hide();
+                ctx.set_handled();
+            }
+
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+fn raise(ctx: &mut EventCtx) {
+    ctx.window().set_window_state(WindowState::Restored);
+    ctx.window().bring_to_front_and_focus();
+}
+
+/// the top left corner is at the `pointer`, but the window is kept inside the monitor
+fn popup_position(pointer: Point, size: Size) -> Point {
+    let work_rect = Screen::get_monitors()
+        .iter()
+        .map(|monitor| monitor.virtual_work_rect())
+        .find(|rect| rect.contains(pointer));
+
+    match work_rect {
+        Some(Rect { x0, y0, x1, y1 }) => Point::new(
+            pointer.x.min(x1 - size.width).max(x0),
+            pointer.y.min(y1 - size.height).max(y0),
+        ),
+        None => pointer,
+    }
+}
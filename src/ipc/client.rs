@@ -0,0 +1,63 @@
+use std::os::unix::net::UnixStream;
+
+use anyhow::{anyhow, Result};
+
+use super::{read_message, socket_path, write_message, History, Request, Response};
+use crate::storage;
+
+/// the connection to the running instance
+pub struct Client {
+    stream: UnixStream,
+}
+
+impl Client {
+    pub fn connect() -> Result<Self> {
+        let path = socket_path();
+        let stream = UnixStream::connect(&path).map_err(|err| {
+            anyhow!(
+                "connect {} failed, is history_clipboard running? {}",
+                path.display(),
+                err
+            )
+        })?;
+
+        Ok(Self { stream })
+    }
+
+    /// send the `request` with its `data`, and wait the response, the error response is
+    /// returned as Err
+    pub fn call(&mut self, request: &Request, data: &[u8]) -> Result<(Response, Vec<u8>)> {
+        write_message(&mut self.stream, request, data)?;
+
+        match read_message(&mut self.stream)? {
+            None => Err(anyhow!("the running instance closed the connection")),
+            Some((Response::Error { message }, _)) => Err(anyhow!(message)),
+            Some(response) => Ok(response),
+        }
+    }
+
+    /// subscribe the history of the daemon, the current one is returned and the later ones are
+    /// read by `next_history`, it fails when the running instance is not a daemon
+    pub fn subscribe(&mut self) -> Result<History> {
+        write_message(&mut self.stream, &Request::Subscribe, &[])?;
+
+        self.next_history()?
+            .ok_or_else(|| anyhow!("the running instance closed the connection"))
+    }
+
+    /// wait the changed history, None when the daemon is closed
+    pub fn next_history(&mut self) -> Result<Option<History>> {
+        match read_message(&mut self.stream)? {
+            None => Ok(None),
+
+            Some((Response::History { status }, data)) => Ok(Some(History {
+                status,
+                entries: storage::decode_entries(&data)?,
+            })),
+
+            Some((Response::Error { message }, _)) => Err(anyhow!(message)),
+
+            Some((response, _)) => Err(anyhow!("unexpected response {:?}", response)),
+        }
+    }
+}
@@ -0,0 +1,638 @@
+//! the local socket `$XDG_RUNTIME_DIR/history_clipboard.sock` to control a running instance
+//!
+//! a message is a json frame followed by a data frame, the data frame carries the content bytes
+//! and it is empty for the other messages. every frame is prefixed by its u32 little endian
+//! length. a connection sends a request and waits its response, until it is closed
+//!
+//! the json object has the `version` of the protocol, and the `request` or `response` kind with
+//! its fields, e.g. `{"version":1,"request":"get","id":42,"target":null}`. the server answers
+//! the request of another version by an error response and closes the connection
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::{env, fmt};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use druid::im::Vector;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub use self::client::Client;
+pub use self::server::{lock_instance, serve, InstanceLock};
+use crate::clipboard::{self, Content, Entry, Removed, Selection};
+use crate::storage::{self, Prompt};
+
+mod client;
+mod server;
+
+/// bump it when a message is changed incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const SOCKET_NAME: &str = "history_clipboard.sock";
+/// refuse the frame larger than it, the peer is broken
+const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+const MAX_PREVIEW_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// list the entries, newest first
+    List,
+    /// get the data of the entry `target`, the content is returned in its own format when the
+    /// target is None. the entries are referred by their [`EntryInfo::id`]
+    Get {
+        id: u64,
+        target: Option<String>,
+    },
+    /// restore the entry to the clipboard
+    Copy {
+        id: u64,
+    },
+    Delete {
+        id: u64,
+    },
+    Clear,
+    /// add the content in the data frame to the history, and copy it to the clipboard
+    Push {
+        target: String,
+    },
+    /// copy the entry encoded in the data frame to the clipboard
+    Restore,
+    /// delete the entry encoded in the data frame, it is found by its id
+    Remove,
+    /// pin or unpin the entry encoded in the data frame as its pinned flag
+    Pin,
+    /// send the history now and whenever it is changed, only the daemon serves it
+    Subscribe,
+    /// the passphrase is empty when the key is in the keyring, it is answered after the storage
+    /// is unlocked or refuses it, only the daemon serves it
+    Unlock {
+        passphrase: String,
+    },
+    Lock,
+    /// raise the window, only the gui serves it
+    Show,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Done,
+    Entries {
+        entries: Vec<EntryInfo>,
+    },
+    /// the data is in the data frame
+    Data {
+        target: String,
+    },
+    Error {
+        message: String,
+    },
+    /// the entries are encoded in the data frame, they are empty unless the history is open
+    History {
+        status: HistoryStatus,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HistoryStatus {
+    /// the history is not persisted
+    Disabled,
+    /// waiting for the storage
+    Opening,
+    Locked {
+        prompt: Prompt,
+        error: Option<String>,
+    },
+    Unlocked,
+}
+
+impl HistoryStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(self, HistoryStatus::Disabled | HistoryStatus::Unlocked)
+    }
+}
+
+/// the history of the daemon
+#[derive(Debug)]
+pub struct History {
+    pub status: HistoryStatus,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    Text,
+    Html,
+    Files,
+    Image,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryInfo {
+    /// the unique id, it is kept while the entry is in the history
+    pub id: u64,
+    pub kind: ContentKind,
+    pub selection: Selection,
+    /// the first line of the text, or the summary of the files and the image
+    pub preview: String,
+    pub targets: Vec<String>,
+    pub pinned: bool,
+    /// the size of the content in bytes
+    pub size: usize,
+    /// in milliseconds since the unix epoch
+    pub copied_at: u64,
+    pub used_at: u64,
+    pub use_count: u32,
+}
+
+impl EntryInfo {
+    fn new(entry: &Entry) -> Self {
+        let (kind, preview) = match &entry.content {
+            Content::Text(text) => (ContentKind::Text, preview_text(text)),
+            Content::Html(html) => (ContentKind::Html, preview_text(&html.text)),
+            Content::Files(files) => (
+                ContentKind::Files,
+                files
+                    .files
+                    .iter()
+                    .map(|file| file.name.as_ref())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Content::Image(img) => (
+                ContentKind::Image,
+                format!(
+                    "{}x{} {:?} image",
+                    img.image_buf.width(),
+                    img.image_buf.height(),
+                    img.format
+                ),
+            ),
+        };
+
+        Self {
+            id: entry.id,
+            kind,
+            selection: entry.selection,
+            preview,
+            targets: entry
+                .targets
+                .iter()
+                .map(|target| target.name.clone())
+                .collect(),
+            pinned: entry.pinned,
+            size: entry.size,
+            copied_at: entry.copied_at,
+            used_at: entry.used_at,
+            use_count: entry.use_count,
+        }
+    }
+}
+
+/// the peer speaks another protocol version
+#[derive(Debug)]
+pub struct VersionMismatch(u32);
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "protocol version {} is not supported, expect {}",
+            self.0, PROTOCOL_VERSION
+        )
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// another instance serves the socket
+#[derive(Debug)]
+pub struct AlreadyRunning;
+
+impl fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "another instance is running")
+    }
+}
+
+impl std::error::Error for AlreadyRunning {}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    message: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+/// the request received by the server, the handler sends the response by `reply_sender`
+pub struct Call {
+    pub request: Request,
+    pub data: Vec<u8>,
+    pub reply_sender: Sender<(Response, Vec<u8>)>,
+}
+
+/// handle the `request` on the history `contents` which keeps `max_size` entries, the entry to
+/// copy is sent to `restore_sender`, and the deleted ones are sent to `removed_sender`. the
+/// clipboard sends the copied entry back, so it is added or moved to the history front later
+pub fn handle(
+    request: Request,
+    data: Vec<u8>,
+    contents: &mut Vector<Entry>,
+    max_size: usize,
+    restore_sender: &Sender<Entry>,
+    removed_sender: &Sender<Removed>,
+) -> (Response, Vec<u8>) {
+    let result = match request {
+        Request::List => Ok((
+            Response::Entries {
+                entries: contents.iter().map(EntryInfo::new).collect(),
+            },
+            vec![],
+        )),
+
+        Request::Get { id, target } => entry_index(contents, id).and_then(|index| {
+            let entry = &contents[index];
+
+            match target {
+                None => {
+                    let (target, data) = entry.content.to_data();
+
+                    Ok((
+                        Response::Data {
+                            target: target.to_string(),
+                        },
+                        data,
+                    ))
+                }
+
+                Some(target) => entry
+                    .targets
+                    .iter()
+                    .find(|entry_target| entry_target.name == target)
+                    .map(|entry_target| {
+                        (
+                            Response::Data {
+                                target: target.clone(),
+                            },
+                            entry_target.data.get().to_vec(),
+                        )
+                    })
+                    .ok_or_else(|| anyhow!("entry {} has no target {}", id, target)),
+            }
+        }),
+
+        Request::Copy { id } => entry_index(contents, id).and_then(|index| {
+            restore_sender
+                .send(contents[index].clone())
+                .map_err(|_| anyhow!("the clipboard is not running"))?;
+
+            Ok((Response::Done, vec![]))
+        }),
+
+        Request::Delete { id } => entry_index(contents, id).map(|index| {
+            let _ = removed_sender.send(Removed::Entry(contents.remove(index)));
+
+            (Response::Done, vec![])
+        }),
+
+        Request::Clear => {
+            contents.clear();
+            let _ = removed_sender.send(Removed::All);
+
+            Ok((Response::Done, vec![]))
+        }
+
+        Request::Push { target } => Content::from_data(&target, data)
+            .ok_or_else(|| anyhow!("the data is not a supported {} content", target))
+            .and_then(|content| {
+                restore_sender
+                    .send(Entry::from_content(Selection::Clipboard, content))
+                    .map_err(|_| anyhow!("the clipboard is not running"))?;
+
+                Ok((Response::Done, vec![]))
+            }),
+
+        Request::Restore => storage::decode_entries(&data).and_then(|entries| {
+            let entry = entries
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("no entry to restore"))?;
+
+            restore_sender
+                .send(entry)
+                .map_err(|_| anyhow!("the clipboard is not running"))?;
+
+            Ok((Response::Done, vec![]))
+        }),
+
+        Request::Remove => find_decoded_entry(contents, &data).map(|(index, _)| {
+            let _ = removed_sender.send(Removed::Entry(contents.remove(index)));
+
+            (Response::Done, vec![])
+        }),
+
+        Request::Pin => find_decoded_entry(contents, &data).map(|(index, entry)| {
+            contents[index].pinned = entry.pinned;
+            // the unpinned entry counts for the size again
+            clipboard::truncate_history(contents, max_size);
+
+            (Response::Done, vec![])
+        }),
+
+        Request::Subscribe | Request::Unlock { .. } | Request::Lock => {
+            Err(anyhow!("the request is only served by the daemon"))
+        }
+
+        Request::Show => Err(anyhow!("the request is only served by the gui")),
+    };
+
+    result.unwrap_or_else(|err| {
+        (
+            Response::Error {
+                message: err.to_string(),
+            },
+            vec![],
+        )
+    })
+}
+
+pub fn socket_path() -> PathBuf {
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+
+    match dir {
+        Some(dir) => dir.join(SOCKET_NAME),
+        // the temp dir is shared, so the socket is named by the user
+        None => env::temp_dir().join(format!(
+            "{}-{}",
+            rustix::process::getuid().as_raw(),
+            SOCKET_NAME
+        )),
+    }
+}
+
+fn entry_index(contents: &Vector<Entry>, id: u64) -> Result<usize> {
+    contents
+        .iter()
+        .position(|entry| entry.id == id)
+        .ok_or_else(|| anyhow!("no entry {}", id))
+}
+
+/// find the entry encoded in the `data` by its id
+fn find_decoded_entry(contents: &Vector<Entry>, data: &[u8]) -> Result<(usize, Entry)> {
+    let entry = storage::decode_entries(data)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no entry in the data"))?;
+
+    let index = contents
+        .iter()
+        .position(|other| other.id == entry.id)
+        .ok_or_else(|| anyhow!("the entry is not in the history"))?;
+
+    Ok((index, entry))
+}
+
+fn preview_text(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or_default();
+
+    match line.char_indices().nth(MAX_PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+fn write_message(writer: &mut impl Write, message: &impl Serialize, data: &[u8]) -> Result<()> {
+    let envelope = Envelope {
+        version: PROTOCOL_VERSION,
+        message,
+    };
+
+    write_frame(writer, &serde_json::to_vec(&envelope)?)?;
+    write_frame(writer, data)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// None means the peer closed the connection, the message of another protocol version is
+/// [`VersionMismatch`]
+fn read_message<T: DeserializeOwned>(reader: &mut impl Read) -> Result<Option<(T, Vec<u8>)>> {
+    let message = match read_frame(reader) {
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+        Ok(message) => message,
+    };
+    let data = read_frame(reader)?;
+
+    // check the version first, the message of another version may not be parsed
+    let version = serde_json::from_slice::<Version>(&message)?.version;
+    if version != PROTOCOL_VERSION {
+        return Err(VersionMismatch(version).into());
+    }
+
+    Ok(Some((serde_json::from_slice(&message)?, data)))
+}
+
+fn write_frame(writer: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+    writer.write_all(frame)
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame size {} is too large", len),
+        ));
+    }
+
+    let mut frame = vec![0; len];
+    reader.read_exact(&mut frame)?;
+
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vector<Entry> {
+        ["newest", "middle", "oldest"]
+            .into_iter()
+            .map(|text| Entry::from_content(Selection::Clipboard, text.to_string().into()))
+            .collect()
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let mut buf = vec![];
+        write_message(
+            &mut buf,
+            &Request::Get {
+                id: 2,
+                target: None,
+            },
+            b"data",
+        )
+        .unwrap();
+        write_message(&mut buf, &Request::Clear, &[]).unwrap();
+
+        let mut reader = buf.as_slice();
+        let (request, data) = read_message::<Request>(&mut reader).unwrap().unwrap();
+        assert!(matches!(
+            request,
+            Request::Get {
+                id: 2,
+                target: None
+            }
+        ));
+        assert_eq!(data, b"data");
+        assert!(matches!(
+            read_message::<Request>(&mut reader).unwrap().unwrap().0,
+            Request::Clear
+        ));
+        assert!(read_message::<Request>(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn handle_requests() {
+        let mut contents = history();
+        let ids = contents.iter().map(|entry| entry.id).collect::<Vec<_>>();
+        let mut oldest = contents[2].clone();
+        let (restore_sender, restore_receiver) = crossbeam_channel::unbounded();
+        let (removed_sender, removed_receiver) = crossbeam_channel::unbounded();
+
+        let mut call = |request, data| {
+            handle(
+                request,
+                data,
+                &mut contents,
+                10,
+                &restore_sender,
+                &removed_sender,
+            )
+        };
+
+        match call(Request::List, vec![]).0 {
+            Response::Entries { entries } => {
+                assert_eq!(
+                    entries
+                        .iter()
+                        .map(|entry| (entry.id, entry.preview.as_str()))
+                        .collect::<Vec<_>>(),
+                    [(ids[0], "newest"), (ids[1], "middle"), (ids[2], "oldest")]
+                );
+                assert_eq!(entries[0].size, 6);
+                assert_eq!(entries[0].use_count, 1);
+            }
+            response => panic!("unexpected response {:?}", response),
+        }
+
+        let (_, data) = call(
+            Request::Get {
+                id: ids[1],
+                target: None,
+            },
+            vec![],
+        );
+        assert_eq!(data, b"middle");
+
+        call(Request::Copy { id: ids[2] }, vec![]);
+        assert!(matches!(
+            &restore_receiver.try_recv().unwrap().content,
+            Content::Text(text) if text.as_ref() == "oldest"
+        ));
+
+        call(Request::Delete { id: ids[0] }, vec![]);
+        assert!(matches!(
+            removed_receiver.try_recv().unwrap(),
+            Removed::Entry(Entry {
+                content: Content::Text(text),
+                ..
+            }) if text.as_ref() == "newest"
+        ));
+        oldest.pinned = true;
+        call(Request::Pin, storage::encode_entries([&oldest]));
+        assert!(matches!(
+            call(Request::List, vec![]).0,
+            Response::Entries { entries } if entries[1].pinned
+        ));
+
+        call(Request::Remove, storage::encode_entries([&oldest]));
+        assert!(removed_receiver.try_recv().is_ok());
+        assert!(matches!(
+            call(Request::Remove, storage::encode_entries([&oldest])).0,
+            Response::Error { .. }
+        ));
+        assert!(matches!(
+            call(Request::Delete { id: ids[0] }, vec![]).0,
+            Response::Error { .. }
+        ));
+
+        call(Request::Clear, vec![]);
+        assert!(matches!(removed_receiver.try_recv().unwrap(), Removed::All));
+        assert!(matches!(
+            call(Request::List, vec![]).0,
+            Response::Entries { entries } if entries.is_empty()
+        ));
+    }
+
+    #[test]
+    fn push_content() {
+        let mut contents = history();
+        let (restore_sender, restore_receiver) = crossbeam_channel::unbounded();
+
+        let (response, _) = handle(
+            Request::Push {
+                target: "text/html".to_string(),
+            },
+            b"<b>pushed</b>".to_vec(),
+            &mut contents,
+            3,
+            &restore_sender,
+            &crossbeam_channel::unbounded().0,
+        );
+        assert!(matches!(response, Response::Done));
+        assert!(matches!(
+            restore_receiver.try_recv().unwrap().content,
+            Content::Html(_)
+        ));
+
+        let (response, _) = handle(
+            Request::Push {
+                target: "image/png".to_string(),
+            },
+            b"not an image".to_vec(),
+            &mut contents,
+            3,
+            &restore_sender,
+            &crossbeam_channel::unbounded().0,
+        );
+        assert!(matches!(response, Response::Error { .. }));
+    }
+
+    #[test]
+    fn refuse_other_version() {
+        let mut buf = vec![];
+        write_frame(&mut buf, br#"{"version":99,"request":"list"}"#).unwrap();
+        write_frame(&mut buf, &[]).unwrap();
+
+        let err = read_message::<Request>(&mut buf.as_slice()).unwrap_err();
+        assert!(err.is::<VersionMismatch>());
+    }
+}
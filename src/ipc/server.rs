@@ -0,0 +1,139 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use tap::TapFallible;
+use tracing::{debug, error, info, warn};
+
+use super::{
+    read_message, socket_path, write_message, AlreadyRunning, Call, Request, Response,
+    VersionMismatch,
+};
+
+/// the lock file next to the socket, only the instance holding it captures the selections and
+/// serves the socket, it is released when dropped
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// take the instance lock, it fails with [`AlreadyRunning`] when another instance holds it
+pub fn lock_instance() -> Result<InstanceLock> {
+    let lock_path = socket_path().with_extension("lock");
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .mode(0o600)
+        .open(&lock_path)
+        .tap_err(|err| error!(%err, ?lock_path, "open instance lock failed"))?;
+    if rustix::fs::flock(
+        &lock_file,
+        rustix::fs::FlockOperation::NonBlockingLockExclusive,
+    )
+    .is_err()
+    {
+        return Err(AlreadyRunning.into());
+    }
+
+    Ok(InstanceLock { _file: lock_file })
+}
+
+/// listen the socket while holding the instance lock, the requests of every connection are sent
+/// to `call_sender`
+pub fn serve(_lock: &InstanceLock, call_sender: Sender<Call>) -> Result<()> {
+    let path = socket_path();
+
+    // the socket is left by a crashed instance, nobody listens it while we hold the lock
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+
+    let listener =
+        UnixListener::bind(&path).tap_err(|err| error!(%err, ?path, "bind ipc socket failed"))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+    info!(?path, "listen ipc socket");
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Err(err) => warn!(%err, "accept ipc connection failed"),
+
+                Ok(stream) => {
+                    let call_sender = call_sender.clone();
+
+                    thread::spawn(move || {
+                        if let Err(err) = serve_connection(stream, &call_sender) {
+                            debug!(?err, "ipc connection broken");
+                        }
+                    });
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve_connection(mut stream: UnixStream, call_sender: &Sender<Call>) -> Result<()> {
+    loop {
+        let (request, data) = match read_message::<Request>(&mut stream) {
+            Ok(None) => return Ok(()),
+
+            Err(err) if err.is::<VersionMismatch>() => {
+                let response = Response::Error {
+                    message: err.to_string(),
+                };
+
+                return write_message(&mut stream, &response, &[]);
+            }
+
+            Err(err) => return Err(err),
+
+            Ok(Some(message)) => message,
+        };
+
+        debug!(?request, "receive ipc request");
+
+        // the subscriber gets a response for every change until it closes the connection
+        let subscribe = matches!(request, Request::Subscribe);
+        let (reply_sender, reply_receiver) = if subscribe {
+            crossbeam_channel::unbounded()
+        } else {
+            crossbeam_channel::bounded(1)
+        };
+        call_sender
+            .send(Call {
+                request,
+                data,
+                reply_sender,
+            })
+            .map_err(|_| anyhow!("the history is closed"))?;
+
+        if subscribe {
+            for (response, data) in reply_receiver {
+                write_message(&mut stream, &response, &data)?;
+            }
+
+            return Ok(());
+        }
+
+        // the handler is gone when the app is exiting
+        let (response, data) = reply_receiver.recv().unwrap_or_else(|_| {
+            (
+                Response::Error {
+                    message: "the history is closed".to_string(),
+                },
+                vec![],
+            )
+        });
+
+        write_message(&mut stream, &response, &data)?;
+    }
+}
@@ -2,43 +2,387 @@ use std::sync::Arc;
 use std::thread;
 
 use anyhow::Result;
-use druid::{AppLauncher, Env, Size, WindowDesc};
+use clap::Parser;
+use crossbeam_channel::{select, Receiver, Sender};
+use druid::{AppLauncher, Env, Point, Size, WindowDesc};
 use tap::TapFallible;
-use tracing::error;
+use tracing::{debug, error, info, warn};
 
+use crate::cli::Cli;
+use crate::clipboard::{Entry, IgnoreRules, Limits, Removed};
+use crate::config::{Config, Hotkey};
+use crate::ipc::Request;
+
+mod cli;
 mod clipboard;
+mod config;
+mod daemon;
 mod gui;
+mod ipc;
+mod storage;
 
 pub fn run() -> Result<()> {
-    let window = WindowDesc::new(gui::new_ui())
-        .title("History Clipboard")
-        .window_size(Size {
-            width: 350.0,
-            height: 500.0,
-        });
-    let launcher = AppLauncher::with_window(window);
+    let cli = Cli::parse();
+
+    if cli.daemon {
+        return daemon::run();
+    }
+
+    match cli.command {
+        None | Some(cli::Command::Gui) => run_gui(),
+        Some(command) => cli::run(command),
+    }
+}
+
+fn run_gui() -> Result<()> {
+    let config = Config::load().tap_err(|err| error!(%err, "load config failed"))?;
+
+    if let Ok(mut client) = ipc::Client::connect() {
+        // the running gui shows its window instead
+        match client.call(&Request::Show, &[]) {
+            Ok(_) => {
+                info!("the running instance shows its window, exit");
+                return Ok(());
+            }
+            Err(err) => debug!(%err, "the running instance has no window"),
+        }
+
+        // show the history of the daemon
+        match client.subscribe() {
+            Ok(history) => return run_attached_gui(config, client, history),
+            Err(err) => debug!(%err, "the running instance is not a daemon"),
+        }
+    }
+
+    // two instances would capture the same contents and fight for the selections
+    let lock = match ipc::lock_instance() {
+        Err(err) if err.is::<ipc::AlreadyRunning>() => {
+            info!("another instance is running, exit");
+            return Ok(());
+        }
+        result => result?,
+    };
+
+    let (call_sender, call_receiver) = crossbeam_channel::unbounded();
+    if let Err(err) = ipc::serve(&lock, call_sender) {
+        warn!(?err, "serve ipc failed, the cli can't reach this instance");
+    }
+
+    let (storage_sender, storage_event_receiver, persisted) = spawn_storage();
+
+    let launcher = new_launcher(&config);
     let event_sink = launcher.get_external_handle();
 
     let (new_content_sender, new_content_receiver) = crossbeam_channel::unbounded();
     let (content_sender, content_receiver) = crossbeam_channel::unbounded();
+    let (removed_sender, removed_receiver) = crossbeam_channel::unbounded();
 
-    // configure_env need 'static
-    let new_content_sender: &'static mut _ = Box::leak(Box::new(Arc::new(new_content_sender)));
+    let ipc_event_sink = event_sink.clone();
+    let restore_sender = new_content_sender.clone();
+    let ipc_removed_sender = removed_sender.clone();
+    thread::spawn(|| {
+        gui::handle_calls(
+            ipc_event_sink,
+            call_receiver,
+            restore_sender,
+            ipc_removed_sender,
+        );
+    });
+
+    let storage_event_sink = event_sink.clone();
+    thread::spawn(|| {
+        gui::update_lock(storage_event_sink, storage_event_receiver);
+    });
+
+    let config_event_sink = event_sink.clone();
+    let (gui_config_sender, gui_config_receiver) = crossbeam_channel::unbounded();
+    let (ignore_receiver, limits_receiver) = watch_config(&config, gui_config_sender);
+    thread::spawn(|| {
+        gui::update_config(config_event_sink, gui_config_receiver);
+    });
+
+    let clipboard_event_sink = event_sink.clone();
+    thread::spawn(|| {
+        gui::update_clipboard(clipboard_event_sink, content_receiver);
+    });
+
+    let hotkey_presses = spawn_clipboard(
+        &config,
+        config.window.hotkey.as_ref(),
+        content_sender,
+        new_content_receiver,
+        ignore_receiver,
+        limits_receiver,
+        removed_receiver,
+    )?;
+    thread::spawn(|| {
+        gui::popup(event_sink, hotkey_presses);
+    });
+
+    let gui_data = gui::Clipboard::new(&config, persisted);
+    // the pins are saved with the history
+    let (pinned_sender, _) = crossbeam_channel::unbounded();
+
+    launch(
+        launcher,
+        gui_data,
+        new_content_sender,
+        storage_sender,
+        removed_sender,
+        pinned_sender,
+    )
+}
+
+/// show the `history` of the daemon subscribed by `client`, the entries to paste, remove or pin
+/// and the lock commands are sent to the daemon
+fn run_attached_gui(config: Config, mut client: ipc::Client, history: ipc::History) -> Result<()> {
+    let launcher = new_launcher(&config);
+    let event_sink = launcher.get_external_handle();
+
+    let (history_sender, history_receiver) = crossbeam_channel::unbounded();
+    let _ = history_sender.send(history);
+    thread::spawn(move || loop {
+        match client.next_history() {
+            Ok(Some(history)) => {
+                if history_sender.send(history).is_err() {
+                    return;
+                }
+            }
+
+            Ok(None) => {
+                warn!("the daemon is closed");
+                return;
+            }
+
+            Err(err) => {
+                error!(?err, "receive history from the daemon failed");
+                return;
+            }
+        }
+    });
+
+    let history_event_sink = event_sink.clone();
+    thread::spawn(|| {
+        gui::update_history(history_event_sink, history_receiver);
+    });
+
+    let (new_content_sender, new_content_receiver) = crossbeam_channel::unbounded();
+    let (storage_sender, storage_receiver) = crossbeam_channel::unbounded();
+    let (removed_sender, removed_receiver) = crossbeam_channel::unbounded();
+    let (pinned_sender, pinned_receiver) = crossbeam_channel::unbounded();
+    thread::spawn(|| {
+        let _ = forward_to_daemon(
+            new_content_receiver,
+            storage_receiver,
+            removed_receiver,
+            pinned_receiver,
+        )
+        .tap_err(|err| error!(?err, "connect the daemon failed"));
+    });
 
+    // the daemon has no window to pop up, the hotkey is grabbed here
+    if let Some(hotkey) = &config.window.hotkey {
+        match clipboard::watch_hotkey(hotkey) {
+            Err(err) => warn!(%err, ?hotkey, "grab hotkey failed"),
+
+            Ok(presses) => {
+                let popup_event_sink = event_sink.clone();
+                thread::spawn(|| {
+                    gui::popup(popup_event_sink, presses);
+                });
+            }
+        }
+    }
+
+    // only the theme is applied, the daemon applies the others
+    let config_receiver = match Config::path() {
+        Some(path) => config::watch(path, config.clone()),
+        None => crossbeam_channel::never(),
+    };
     thread::spawn(|| {
-        gui::update_clipboard(event_sink, content_receiver);
+        gui::update_config(event_sink, config_receiver);
     });
 
-    let mut clipboard = clipboard::Clipboard::new(content_sender, new_content_receiver)
-        .tap_err(|err| error!(%err, "create clipboard failed"))?;
+    let gui_data = gui::Clipboard::new(&config, true);
+
+    launch(
+        launcher,
+        gui_data,
+        new_content_sender,
+        storage_sender,
+        removed_sender,
+        pinned_sender,
+    )
+}
+
+fn forward_to_daemon(
+    content_receiver: Receiver<Entry>,
+    storage_receiver: Receiver<storage::Command>,
+    removed_receiver: Receiver<Removed>,
+    pinned_receiver: Receiver<Entry>,
+) -> Result<()> {
+    let mut client = ipc::Client::connect()?;
+
+    loop {
+        let (request, data) = select! {
+            recv(content_receiver) -> entry => match entry {
+                Ok(entry) => (Request::Restore, storage::encode_entries([&entry])),
+                Err(_) => return Ok(()),
+            },
+
+            recv(storage_receiver) -> command => match command {
+                Ok(storage::Command::Unlock(passphrase)) => {
+                    (Request::Unlock { passphrase }, vec![])
+                }
+                Ok(storage::Command::Lock) => (Request::Lock, vec![]),
+                // the daemon saves its history
+                Ok(storage::Command::Save(_)) => continue,
+                Err(_) => return Ok(()),
+            },
+
+            recv(removed_receiver) -> removed => match removed {
+                Ok(Removed::Entry(entry)) => (Request::Remove, storage::encode_entries([&entry])),
+                Ok(Removed::All) => (Request::Clear, vec![]),
+                Err(_) => return Ok(()),
+            },
+
+            recv(pinned_receiver) -> entry => match entry {
+                Ok(entry) => (Request::Pin, storage::encode_entries([&entry])),
+                Err(_) => return Ok(()),
+            },
+        };
+
+        match client.call(&request, &data) {
+            // the refused passphrase is shown by the published history
+            Err(err) if matches!(request, Request::Unlock { .. }) => {
+                debug!(%err, "unlock the history of the daemon failed");
+            }
+            Err(err) => warn!(?err, ?request, "send request to the daemon failed"),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// spawn the storage thread, the history is not persisted when it can't be opened
+fn spawn_storage() -> (Sender<storage::Command>, Receiver<storage::Event>, bool) {
+    let (storage_sender, storage_receiver) = crossbeam_channel::unbounded();
+    let (storage_event_sender, storage_event_receiver) = crossbeam_channel::unbounded();
+
+    let persisted = match storage::Storage::open() {
+        Err(err) => {
+            error!(
+                ?err,
+                "open history storage failed, the history won't be saved"
+            );
+
+            false
+        }
+
+        Ok(storage) => {
+            thread::spawn(move || storage::run(storage, storage_receiver, storage_event_sender));
 
-    let _clipboard_thread = thread::spawn(move || clipboard.run());
+            true
+        }
+    };
 
-    let gui_data = gui::Clipboard::new(20);
+    (storage_sender, storage_event_receiver, persisted)
+}
+
+/// watch the config file, the reloaded configs are sent to `config_sender`, and the returned
+/// receivers get the ignore rules and the size limits starting with the current ones
+fn watch_config(
+    config: &Config,
+    config_sender: Sender<Config>,
+) -> (Receiver<IgnoreRules>, Receiver<Limits>) {
+    let (ignore_sender, ignore_receiver) = crossbeam_channel::unbounded();
+    let (limits_sender, limits_receiver) = crossbeam_channel::unbounded();
+    let _ = ignore_sender.send(config.ignore.rules());
+    let _ = limits_sender.send(config.clipboard.limits());
+
+    if let Some(path) = Config::path() {
+        let config_receiver = config::watch(path, config.clone());
+
+        thread::spawn(move || {
+            for config in config_receiver {
+                let _ = ignore_sender.send(config.ignore.rules());
+                let _ = limits_sender.send(config.clipboard.limits());
+                let _ = config_sender.send(config);
+            }
+        });
+    }
+
+    (ignore_receiver, limits_receiver)
+}
+
+/// spawn the clipboard thread, the hotkey presses are sent to the returned receiver when the
+/// `hotkey` is grabbed
+fn spawn_clipboard(
+    config: &Config,
+    hotkey: Option<&Hotkey>,
+    content_sender: Sender<Entry>,
+    new_content_receiver: Receiver<Entry>,
+    ignore_receiver: Receiver<IgnoreRules>,
+    limits_receiver: Receiver<Limits>,
+    removed_receiver: Receiver<Removed>,
+) -> Result<Receiver<(f64, f64)>> {
+    let backend = clipboard::new_backend(
+        &config.clipboard.selections,
+        config.clipboard.poll_interval(),
+        config.clipboard.load_timeout(),
+        hotkey,
+    )
+    .tap_err(|err| error!(%err, "create clipboard backend failed"))?;
+    let hotkey_presses = backend.hotkey_presses();
+
+    let mut clipboard = clipboard::Clipboard::new(
+        backend,
+        config.clipboard.limits(),
+        content_sender,
+        new_content_receiver,
+        ignore_receiver,
+        limits_receiver,
+        removed_receiver,
+    );
+
+    thread::spawn(move || clipboard.run());
+
+    Ok(hotkey_presses)
+}
+
+fn new_launcher(config: &Config) -> AppLauncher<gui::Clipboard> {
+    let mut window = WindowDesc::new(gui::new_ui())
+        .title(config.window.title.clone())
+        .window_size(Size {
+            width: config.window.width,
+            height: config.window.height,
+        });
+    if let (Some(x), Some(y)) = (config.window.x, config.window.y) {
+        window = window.set_position(Point::new(x, y));
+    }
+
+    AppLauncher::with_window(window)
+}
+
+fn launch(
+    launcher: AppLauncher<gui::Clipboard>,
+    gui_data: gui::Clipboard,
+    new_content_sender: Sender<Entry>,
+    storage_sender: Sender<storage::Command>,
+    removed_sender: Sender<Removed>,
+    pinned_sender: Sender<Entry>,
+) -> Result<()> {
+    // configure_env need 'static
+    let new_content_sender: &'static mut _ = Box::leak(Box::new(Arc::new(new_content_sender)));
+    let storage_sender: &'static mut _ = Box::leak(Box::new(Arc::new(storage_sender)));
+    let removed_sender: &'static mut _ = Box::leak(Box::new(Arc::new(removed_sender)));
+    let pinned_sender: &'static mut _ = Box::leak(Box::new(Arc::new(pinned_sender)));
 
     launcher
         .configure_env(move |env: &mut Env, _state: &gui::Clipboard| {
             env.set(gui::CONTENT_SENDER, new_content_sender.clone());
+            env.set(gui::STORAGE_SENDER, storage_sender.clone());
+            env.set(gui::REMOVED_SENDER, removed_sender.clone());
+            env.set(gui::PINNED_SENDER, pinned_sender.clone());
         })
         .log_to_console()
         .launch(gui_data)?;
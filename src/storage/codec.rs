@@ -0,0 +1,259 @@
+//! the binary encoding of the history index, the numbers are little endian and the bytes are
+//! prefixed by their length
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use image::ImageFormat;
+
+use crate::clipboard::{
+    self, Content, ContentFile, ContentFiles, ContentHtml, Entry, FileOperation, Selection, Target,
+};
+
+/// the md5 sum which names a blob
+pub type Sum = [u8; 16];
+
+/// the bits of the entry flags, the entries of the schema version 1 have no flags
+const PINNED_FLAG: u8 = 1;
+
+/// an entry whose blobs are not loaded yet
+#[derive(Debug)]
+pub struct EntryRecord {
+    pub selection: Selection,
+    pub content: ContentRecord,
+    /// (target name, blob sum)
+    pub targets: Vec<(String, Sum)>,
+    pub pinned: bool,
+    pub id: u64,
+    pub copied_at: u64,
+    pub used_at: u64,
+    pub use_count: u32,
+}
+
+#[derive(Debug)]
+pub enum ContentRecord {
+    Text(Arc<str>),
+    Html(ContentHtml),
+    Files(ContentFiles),
+    /// the raw image is the blob named by the sum
+    Image(ImageFormat, Sum),
+}
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn put_raw(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    pub fn put_u8(&mut self, n: u8) {
+        self.buf.push(n);
+    }
+
+    pub fn put_u32(&mut self, n: u32) {
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, n: u64) {
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+
+    pub fn put_bytes(&mut self, data: &[u8]) {
+        self.put_u32(data.len() as _);
+        self.put_raw(data);
+    }
+
+    pub fn put_str(&mut self, s: &str) {
+        self.put_bytes(s.as_bytes());
+    }
+
+    /// the `targets` are the saved ones of the `entry`, and the `sums` are their blob sums
+    pub fn put_entry(&mut self, entry: &Entry, targets: &[&Target], sums: &[Sum]) {
+        self.put_u8(match entry.selection {
+            Selection::Clipboard => 0,
+            Selection::Primary => 1,
+            Selection::Secondary => 2,
+        });
+
+        match &entry.content {
+            Content::Text(text) => {
+                self.put_u8(0);
+                self.put_str(text);
+            }
+
+            Content::Html(html) => {
+                self.put_u8(1);
+                self.put_str(&html.html);
+                self.put_str(&html.text);
+            }
+
+            Content::Files(files) => {
+                self.put_u8(2);
+                self.put_u8(match files.operation {
+                    FileOperation::Copy => 0,
+                    FileOperation::Cut => 1,
+                });
+                self.put_u32(files.files.len() as _);
+                for file in files.files.iter() {
+                    self.put_str(&file.uri);
+                }
+            }
+
+            Content::Image(img) => {
+                self.put_u8(3);
+                self.put_str(img.format.extensions_str()[0]);
+                self.put_raw(&img.sum);
+            }
+        }
+
+        self.put_u32(targets.len() as _);
+        for (target, sum) in targets.iter().zip(sums) {
+            self.put_str(&target.name);
+            self.put_raw(sum);
+        }
+
+        self.put_u8(if entry.pinned { PINNED_FLAG } else { 0 });
+
+        self.put_u64(entry.id);
+        self.put_u64(entry.copied_at);
+        self.put_u64(entry.used_at);
+        self.put_u32(entry.use_count);
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn take_raw(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(anyhow!("truncated history data"));
+        }
+
+        let (data, rest) = self.buf.split_at(len);
+        self.buf = rest;
+
+        Ok(data)
+    }
+
+    pub fn take_u8(&mut self) -> Result<u8> {
+        Ok(self.take_raw(1)?[0])
+    }
+
+    pub fn take_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take_raw(4)?.try_into()?))
+    }
+
+    pub fn take_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take_raw(8)?.try_into()?))
+    }
+
+    pub fn take_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.take_u32()?;
+
+        self.take_raw(len as _)
+    }
+
+    pub fn take_str(&mut self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.take_bytes()?)?)
+    }
+
+    pub fn take_sum(&mut self) -> Result<Sum> {
+        Ok(self.take_raw(16)?.try_into()?)
+    }
+
+    /// take the entry written by the schema `version`
+    pub fn take_entry(&mut self, version: u32) -> Result<EntryRecord> {
+        let selection = match self.take_u8()? {
+            0 => Selection::Clipboard,
+            1 => Selection::Primary,
+            2 => Selection::Secondary,
+            n => return Err(anyhow!("unknown selection {}", n)),
+        };
+
+        let content = match self.take_u8()? {
+            0 => ContentRecord::Text(self.take_str()?.into()),
+
+            1 => ContentRecord::Html(ContentHtml {
+                html: self.take_str()?.into(),
+                text: self.take_str()?.into(),
+            }),
+
+            2 => {
+                let operation = match self.take_u8()? {
+                    0 => FileOperation::Copy,
+                    1 => FileOperation::Cut,
+                    n => return Err(anyhow!("unknown file operation {}", n)),
+                };
+
+                let count = self.take_u32()?;
+                let files = (0..count)
+                    .map(|_| Ok(ContentFile::new(self.take_str()?)))
+                    .collect::<Result<Vec<_>>>()?;
+
+                ContentRecord::Files(ContentFiles {
+                    operation,
+                    files: files.into(),
+                })
+            }
+
+            3 => {
+                let extension = self.take_str()?;
+                let format = ImageFormat::from_extension(extension)
+                    .ok_or_else(|| anyhow!("unknown image format {}", extension))?;
+
+                ContentRecord::Image(format, self.take_sum()?)
+            }
+
+            n => return Err(anyhow!("unknown content kind {}", n)),
+        };
+
+        let count = self.take_u32()?;
+        let targets = (0..count)
+            .map(|_| Ok((self.take_str()?.to_string(), self.take_sum()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let flags = if version >= 2 { self.take_u8()? } else { 0 };
+
+        // the older entries get a new id, and they are taken as copied when they are loaded
+        let (id, copied_at, used_at, use_count) = if version >= 3 {
+            (
+                self.take_u64()?,
+                self.take_u64()?,
+                self.take_u64()?,
+                self.take_u32()?,
+            )
+        } else {
+            let now = clipboard::now_millis();
+
+            (clipboard::new_entry_id(), now, now, 1)
+        };
+
+        Ok(EntryRecord {
+            selection,
+            content,
+            targets,
+            pinned: flags & PINNED_FLAG != 0,
+            id,
+            copied_at,
+            used_at,
+            use_count,
+        })
+    }
+}
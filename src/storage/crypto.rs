@@ -0,0 +1,244 @@
+//! the encryption of the history, the files are sealed by XChaCha20-Poly1305, the key is derived
+//! from a passphrase by argon2id, or generated randomly and kept in the Secret Service keyring
+
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use md5::{Digest, Md5};
+
+use super::codec::{Reader, Sum, Writer};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+
+const KEY_MAGIC: &[u8; 4] = b"HCKY";
+const KEY_VERSION: u32 = 1;
+/// it is sealed in the key file, to check the key before opening the history
+const VERIFIER: &[u8] = b"history_clipboard";
+
+const KEYRING_SERVICE: &str = "history_clipboard";
+const KEYRING_USER: &str = "history key";
+
+pub struct Cipher {
+    key: [u8; KEY_LEN],
+    aead: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn new(key: [u8; KEY_LEN]) -> Self {
+        Self {
+            key,
+            aead: XChaCha20Poly1305::new(&key.into()),
+        }
+    }
+
+    /// seal the `data`, the random nonce is put before the cipher text
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .aead
+            .encrypt(&nonce, data)
+            .map_err(|_| anyhow!("encrypt history failed"))?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&sealed);
+
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("encrypted history data is truncated"));
+        }
+
+        let (nonce, sealed) = data.split_at(NONCE_LEN);
+
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| anyhow!("decrypt history failed, the key is wrong or the data is broken"))
+    }
+
+    /// the blob name of the content `sum`, it is keyed so the name doesn't reveal the content
+    pub fn blob_name(&self, sum: &Sum) -> Sum {
+        let mut hasher = Md5::new();
+        hasher.update(self.key);
+        hasher.update(sum);
+
+        hasher.finalize().into()
+    }
+}
+
+/// where the key comes from
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    Passphrase {
+        salt: [u8; SALT_LEN],
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    Keyring,
+}
+
+/// the key file describes the key without containing it
+#[derive(Debug, Clone)]
+pub struct KeyFile {
+    pub source: KeySource,
+    /// the sealed [`VERIFIER`]
+    verifier: Vec<u8>,
+}
+
+impl KeyFile {
+    /// create a passphrase key
+    pub fn new_passphrase(passphrase: &str) -> Result<(Self, Cipher)> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        let source = KeySource::Passphrase {
+            salt,
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        };
+        let cipher = Cipher::new(derive_key(&source, passphrase)?);
+
+        Ok((Self::sealed(source, &cipher)?, cipher))
+    }
+
+    /// create a random key and keep it in the keyring
+    pub fn new_keyring() -> Result<(Self, Cipher)> {
+        let mut key = [0; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+
+        keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.set_password(&hex(&key))?;
+
+        let cipher = Cipher::new(key);
+
+        Ok((Self::sealed(KeySource::Keyring, &cipher)?, cipher))
+    }
+
+    fn sealed(source: KeySource, cipher: &Cipher) -> Result<Self> {
+        Ok(Self {
+            source,
+            verifier: cipher.encrypt(VERIFIER)?,
+        })
+    }
+
+    /// get the key from the passphrase or the keyring, the `passphrase` is ignored when the key
+    /// is in the keyring
+    pub fn unlock(&self, passphrase: &str) -> Result<Cipher> {
+        let key = match &self.source {
+            KeySource::Passphrase { .. } => derive_key(&self.source, passphrase)?,
+
+            KeySource::Keyring => {
+                let key = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.get_password()?;
+
+                unhex(&key)
+                    .and_then(|key| key.try_into().ok())
+                    .ok_or_else(|| anyhow!("the key in the keyring is broken"))?
+            }
+        };
+
+        let cipher = Cipher::new(key);
+        match cipher.decrypt(&self.verifier) {
+            Ok(verifier) if verifier == VERIFIER => Ok(cipher),
+            _ => Err(anyhow!("wrong passphrase")),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.put_raw(KEY_MAGIC);
+        writer.put_u32(KEY_VERSION);
+
+        match &self.source {
+            KeySource::Passphrase {
+                salt,
+                m_cost,
+                t_cost,
+                p_cost,
+            } => {
+                writer.put_u8(0);
+                writer.put_raw(salt);
+                writer.put_u32(*m_cost);
+                writer.put_u32(*t_cost);
+                writer.put_u32(*p_cost);
+            }
+
+            KeySource::Keyring => writer.put_u8(1),
+        }
+
+        writer.put_bytes(&self.verifier);
+
+        writer.into_inner()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        if reader.take_raw(KEY_MAGIC.len())? != KEY_MAGIC {
+            return Err(anyhow!("not a history key file"));
+        }
+
+        let version = reader.take_u32()?;
+        if version != KEY_VERSION {
+            return Err(anyhow!("unknown key file version {}", version));
+        }
+
+        let source = match reader.take_u8()? {
+            0 => KeySource::Passphrase {
+                salt: reader.take_raw(SALT_LEN)?.try_into()?,
+                m_cost: reader.take_u32()?,
+                t_cost: reader.take_u32()?,
+                p_cost: reader.take_u32()?,
+            },
+
+            1 => KeySource::Keyring,
+
+            n => return Err(anyhow!("unknown key source {}", n)),
+        };
+
+        Ok(Self {
+            source,
+            verifier: reader.take_bytes()?.to_vec(),
+        })
+    }
+}
+
+fn derive_key(source: &KeySource, passphrase: &str) -> Result<[u8; KEY_LEN]> {
+    let (salt, params) = match source {
+        KeySource::Passphrase {
+            salt,
+            m_cost,
+            t_cost,
+            p_cost,
+        } => (
+            salt,
+            Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_LEN))
+                .map_err(|err| anyhow!("invalid argon2 params: {}", err))?,
+        ),
+
+        KeySource::Keyring => return Err(anyhow!("the key is not derived from a passphrase")),
+    };
+
+    let mut key = [0; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("derive key from passphrase failed: {}", err))?;
+
+    Ok(key)
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,786 @@
+//! persist the history to `$XDG_DATA_HOME/history_clipboard`
+//!
+//! the `history` file is the index of the entries, the target contents are saved as the blobs
+//! named by their md5 sum, so the image blob is named by its `ContentImage::sum`. every file is
+//! written to a temporary file and renamed, the index is renamed after its blobs, so a crash
+//! leaves the old index or the new one with all its blobs
+//!
+//! the index and the blobs are encrypted by the key described in the `key` file, and the blob
+//! names are keyed sums. the plain history written by the older versions is still loaded, and it
+//! is encrypted by the next saving
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fmt};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{Receiver, Sender};
+use druid::im::Vector;
+use druid::Data;
+use serde::{Deserialize, Serialize};
+use tap::TapFallible;
+use tracing::{debug, error, info, warn};
+
+use self::codec::{ContentRecord, EntryRecord, Reader, Sum, Writer};
+use self::crypto::{hex, Cipher, KeyFile, KeySource};
+use crate::clipboard::{self, Content, ContentImage, Entry, Target};
+
+mod codec;
+mod crypto;
+
+const DIR_NAME: &str = "history_clipboard";
+const INDEX_NAME: &str = "history";
+const KEY_NAME: &str = "key";
+const BLOBS_DIR_NAME: &str = "blobs";
+const TMP_SUFFIX: &str = ".tmp";
+
+const MAGIC: &[u8; 4] = b"HCLP";
+/// the magic of the encrypted index, the rest is the sealed plain index
+const SEALED_MAGIC: &[u8; 4] = b"HCLE";
+/// the version of the index format, bump it when the format is changed and keep reading the
+/// old versions
+const SCHEMA_VERSION: u32 = 3;
+
+/// the index is written by a newer version, we must not overwrite it
+#[derive(Debug)]
+pub struct NewerSchemaError(u32);
+
+impl fmt::Display for NewerSchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "history schema version {} is newer than the supported {}",
+            self.0, SCHEMA_VERSION
+        )
+    }
+}
+
+impl std::error::Error for NewerSchemaError {}
+
+/// what is needed to unlock the history
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Prompt {
+    /// there is no key yet and the keyring is unavailable
+    NewPassphrase,
+    Passphrase,
+    /// the keyring is locked or unavailable, retry after unlocking it
+    Keyring,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    /// the passphrase is empty when the key is in the keyring
+    Unlock(String),
+    Lock,
+    /// save the history snapshot, it is ignored when locked
+    Save(Vector<Entry>),
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Locked {
+        prompt: Prompt,
+        error: Option<String>,
+    },
+    /// the saved entries, newest first
+    Unlocked(Vec<Entry>),
+}
+
+pub struct Storage {
+    dir: PathBuf,
+    /// None means locked
+    cipher: Option<Cipher>,
+}
+
+impl Storage {
+    pub fn open() -> Result<Self> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+            .ok_or_else(|| anyhow!("neither XDG_DATA_HOME nor HOME is set"))?;
+
+        Self::with_dir(data_home.join(DIR_NAME))
+    }
+
+    pub fn with_dir(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(dir.join(BLOBS_DIR_NAME))
+            .tap_err(|err| error!(%err, ?dir, "create storage dir failed"))?;
+
+        let storage = Self { dir, cipher: None };
+        storage.remove_tmp_files()?;
+
+        Ok(storage)
+    }
+
+    pub fn prompt(&self) -> Prompt {
+        match self.key_file() {
+            Ok(None) => Prompt::NewPassphrase,
+            Ok(Some(KeyFile {
+                source: KeySource::Keyring,
+                ..
+            })) => Prompt::Keyring,
+            // the broken key file is reported when unlocking
+            Ok(Some(_)) | Err(_) => Prompt::Passphrase,
+        }
+    }
+
+    /// get the key, or create it when there is no key yet, the new key is kept in the keyring
+    /// when the `passphrase` is empty
+    pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
+        let cipher = match self.key_file()? {
+            Some(key_file) => key_file.unlock(passphrase)?,
+
+            None => {
+                let (key_file, cipher) = if passphrase.is_empty() {
+                    KeyFile::new_keyring()?
+                } else {
+                    KeyFile::new_passphrase(passphrase)?
+                };
+
+                write_atomic(&self.dir.join(KEY_NAME), &key_file.encode())?;
+                info!("create history key done");
+
+                cipher
+            }
+        };
+
+        self.cipher = Some(cipher);
+
+        Ok(())
+    }
+
+    pub fn lock(&mut self) {
+        self.cipher = None;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.cipher.is_none()
+    }
+
+    /// load the saved entries, newest first
+    pub fn load(&self) -> Result<Vec<Entry>> {
+        let index = match fs::read(self.index_path()) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+            Ok(index) => index,
+        };
+
+        // the plain index is written by the older versions, its blobs are plain too
+        let sealed = index.starts_with(SEALED_MAGIC);
+        let index = if sealed {
+            self.cipher()?.decrypt(&index[SEALED_MAGIC.len()..])
+        } else {
+            Ok(index)
+        };
+
+        let records = match index.and_then(|index| decode_index(&index)) {
+            Err(err) if err.is::<NewerSchemaError>() => return Err(err),
+
+            Err(err) => {
+                // keep the broken index for investigation, and start with an empty history
+                let broken_path = self.dir.join(format!("{}.broken", INDEX_NAME));
+                error!(%err, ?broken_path, "decode history index failed, move it away");
+
+                fs::rename(self.index_path(), broken_path)?;
+
+                return Ok(vec![]);
+            }
+
+            Ok(records) => records,
+        };
+
+        let entries = records
+            .into_iter()
+            .filter_map(|record| {
+                self.load_entry(record, sealed)
+                    .tap_err(|err| warn!(%err, "load history entry failed, skip it"))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        info!(count = entries.len(), "load history done");
+
+        Ok(entries)
+    }
+
+    /// save the `entries`, the blobs not used by them are removed
+    pub fn save<'a>(&self, entries: impl IntoIterator<Item = &'a Entry>) -> Result<()> {
+        let cipher = self.cipher()?;
+        let mut writer = Writer::default();
+        let mut used_blobs = HashSet::new();
+        let mut count = 0;
+
+        writer.put_raw(MAGIC);
+        writer.put_u32(SCHEMA_VERSION);
+
+        let entries = entries.into_iter().collect::<Vec<_>>();
+        writer.put_u32(entries.len() as _);
+
+        for entry in entries {
+            let targets = saved_targets(entry);
+            let sums = targets
+                .iter()
+                .map(|target| self.save_blob(&target.data.get()))
+                .collect::<Result<Vec<_>>>()?;
+
+            used_blobs.extend(sums.iter().copied());
+            if let Content::Image(img) = &entry.content {
+                // the raw image is not a target when it is created from the content
+                if !sums.contains(&img.sum) {
+                    self.save_blob(&img.raw)?;
+                }
+                used_blobs.insert(img.sum);
+            }
+
+            writer.put_entry(entry, &targets, &sums);
+            count += 1;
+        }
+
+        let mut index = SEALED_MAGIC.to_vec();
+        index.extend_from_slice(&cipher.encrypt(&writer.into_inner())?);
+
+        write_atomic(&self.index_path(), &index)?;
+        self.remove_unused_blobs(&used_blobs)?;
+
+        debug!(count, "save history done");
+
+        Ok(())
+    }
+
+    fn load_entry(&self, record: EntryRecord, sealed: bool) -> Result<Entry> {
+        build_entry(record, |sum| self.load_blob(sum, sealed))
+    }
+
+    fn save_blob(&self, data: &[u8]) -> Result<Sum> {
+        let cipher = self.cipher()?;
+        let sum = clipboard::md5_sum(data);
+        let path = self.blob_path(&cipher.blob_name(&sum));
+
+        // the blob is named by its content, the existing one is the same
+        if !path.exists() {
+            write_atomic(&path, &cipher.encrypt(data)?)?;
+        }
+
+        Ok(sum)
+    }
+
+    fn load_blob(&self, sum: &Sum, sealed: bool) -> Result<Arc<[u8]>> {
+        let data = if sealed {
+            let cipher = self.cipher()?;
+
+            cipher.decrypt(&fs::read(self.blob_path(&cipher.blob_name(sum)))?)?
+        } else {
+            fs::read(self.blob_path(sum))?
+        };
+
+        if clipboard::md5_sum(&data) != *sum {
+            return Err(anyhow!("blob {} is broken", hex(sum)));
+        }
+
+        Ok(data.into())
+    }
+
+    /// remove the blobs not used by the saved index, the plain blobs are removed after they are
+    /// saved encrypted
+    fn remove_unused_blobs(&self, used_blobs: &HashSet<Sum>) -> Result<()> {
+        let cipher = self.cipher()?;
+        let used_names = used_blobs
+            .iter()
+            .map(|sum| hex(&cipher.blob_name(sum)))
+            .collect::<HashSet<_>>();
+
+        for dir_entry in fs::read_dir(self.dir.join(BLOBS_DIR_NAME))? {
+            let dir_entry = dir_entry?;
+
+            if !used_names.contains(dir_entry.file_name().to_string_lossy().as_ref()) {
+                fs::remove_file(dir_entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// remove the temporary files left by a crash
+    fn remove_tmp_files(&self) -> Result<()> {
+        for dir in [self.dir.clone(), self.dir.join(BLOBS_DIR_NAME)] {
+            for dir_entry in fs::read_dir(dir)? {
+                let dir_entry = dir_entry?;
+
+                if dir_entry
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(TMP_SUFFIX)
+                {
+                    debug!(path = ?dir_entry.path(), "remove temporary file");
+
+                    fs::remove_file(dir_entry.path())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn key_file(&self) -> Result<Option<KeyFile>> {
+        match fs::read(self.dir.join(KEY_NAME)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+            Ok(data) => Ok(Some(KeyFile::decode(&data)?)),
+        }
+    }
+
+    fn cipher(&self) -> Result<&Cipher> {
+        self.cipher
+            .as_ref()
+            .ok_or_else(|| anyhow!("the history is locked"))
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join(INDEX_NAME)
+    }
+
+    fn blob_path(&self, name: &Sum) -> PathBuf {
+        self.dir.join(BLOBS_DIR_NAME).join(hex(name))
+    }
+}
+
+/// run the storage commands, the keyring is tried first, and the gui is asked for the passphrase
+/// when it is needed
+pub fn run(mut storage: Storage, command_receiver: Receiver<Command>, event_sender: Sender<Event>) {
+    let event = match storage.prompt() {
+        Prompt::Passphrase => Event::Locked {
+            prompt: Prompt::Passphrase,
+            error: None,
+        },
+
+        // try the keyring, it needs no input
+        Prompt::Keyring | Prompt::NewPassphrase => unlock(&mut storage, ""),
+    };
+    let _ = event_sender.send(event);
+
+    let mut next = command_receiver.recv().ok();
+    while let Some(command) = next.take() {
+        match command {
+            Command::Unlock(passphrase) => {
+                let _ = event_sender.send(unlock(&mut storage, &passphrase));
+            }
+
+            Command::Lock => {
+                storage.lock();
+                info!("history locked");
+
+                let _ = event_sender.send(Event::Locked {
+                    prompt: storage.prompt(),
+                    error: None,
+                });
+            }
+
+            Command::Save(mut entries) => {
+                // only the latest snapshot is saved when they come faster than saving
+                while let Ok(command) = command_receiver.try_recv() {
+                    match command {
+                        Command::Save(latest) => entries = latest,
+                        command => {
+                            next = Some(command);
+                            break;
+                        }
+                    }
+                }
+
+                if storage.is_locked() {
+                    debug!("history is locked, skip saving");
+                } else {
+                    let _ = storage
+                        .save(&entries)
+                        .tap_err(|err| error!(?err, "save history failed"));
+                }
+            }
+        }
+
+        if next.is_none() {
+            next = command_receiver.recv().ok();
+        }
+    }
+}
+
+fn unlock(storage: &mut Storage, passphrase: &str) -> Event {
+    match storage.unlock(passphrase).and_then(|_| storage.load()) {
+        Ok(entries) => Event::Unlocked(entries),
+
+        Err(err) => {
+            error!(?err, "unlock history failed");
+            storage.lock();
+
+            Event::Locked {
+                prompt: storage.prompt(),
+                error: Some(err.to_string()),
+            }
+        }
+    }
+}
+
+/// encode the `entries` with their blobs, to pass them to another process
+pub fn encode_entries<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Vec<u8> {
+    let entries = entries.into_iter().collect::<Vec<_>>();
+    let mut writer = Writer::default();
+    writer.put_u32(entries.len() as _);
+
+    for entry in entries {
+        let targets = saved_targets(entry);
+        let sums = targets
+            .iter()
+            .map(|target| clipboard::md5_sum(&target.data.get()))
+            .collect::<Vec<_>>();
+        writer.put_entry(entry, &targets, &sums);
+
+        // the blobs follow the entry in the order `build_entry` loads them
+        if let Content::Image(img) = &entry.content {
+            writer.put_bytes(&img.raw);
+        }
+        for target in &targets {
+            writer.put_bytes(&target.data.get());
+        }
+    }
+
+    writer.into_inner()
+}
+
+pub fn decode_entries(data: &[u8]) -> Result<Vec<Entry>> {
+    let mut reader = Reader::new(data);
+    let count = reader.take_u32()?;
+
+    let entries = (0..count)
+        .map(|_| {
+            let record = reader.take_entry(SCHEMA_VERSION)?;
+
+            build_entry(record, |sum| {
+                let blob = reader.take_bytes()?;
+                if clipboard::md5_sum(blob) != *sum {
+                    return Err(anyhow!("blob {} is broken", hex(sum)));
+                }
+
+                Ok(blob.into())
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if !reader.is_empty() {
+        return Err(anyhow!("unexpected data after the entries"));
+    }
+
+    Ok(entries)
+}
+
+/// the image blob is loaded first, then the target blobs in order
+/// the targets to save, the converted ones are rebuilt from the content when loading
+fn saved_targets(entry: &Entry) -> Vec<&Target> {
+    entry
+        .targets
+        .iter()
+        .filter(|target| !target.data.is_converted())
+        .collect()
+}
+
+fn build_entry(
+    record: EntryRecord,
+    mut load_blob: impl FnMut(&Sum) -> Result<Arc<[u8]>>,
+) -> Result<Entry> {
+    let content = match record.content {
+        ContentRecord::Text(text) => Content::Text(text),
+        ContentRecord::Html(html) => Content::Html(html),
+        ContentRecord::Files(files) => Content::Files(files),
+        ContentRecord::Image(format, sum) => {
+            let raw = load_blob(&sum)?;
+
+            Content::Image(ContentImage::decode(raw, format, sum)?)
+        }
+    };
+
+    let mut targets = record
+        .targets
+        .into_iter()
+        .map(|(name, sum)| {
+            Ok(Target {
+                name,
+                data: load_blob(&sum)?.into(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if let Content::Image(img) = &content {
+        targets.extend(img.converted_targets(&targets));
+    }
+
+    let mut entry = Entry::new(record.selection, content, targets.into());
+    entry.id = record.id;
+    entry.pinned = record.pinned;
+    entry.copied_at = record.copied_at;
+    entry.used_at = record.used_at;
+    entry.use_count = record.use_count;
+
+    Ok(entry)
+}
+
+fn decode_index(index: &[u8]) -> Result<Vec<EntryRecord>> {
+    let mut reader = Reader::new(index);
+
+    if reader.take_raw(MAGIC.len())? != MAGIC {
+        return Err(anyhow!("not a history index"));
+    }
+
+    let version = reader.take_u32()?;
+    let records = match version {
+        // the version 2 adds the entry flags, and the version 3 adds the id, the times and the
+        // use count
+        1..=3 => {
+            let count = reader.take_u32()?;
+
+            (0..count)
+                .map(|_| reader.take_entry(version))
+                .collect::<Result<Vec<_>>>()?
+        }
+
+        version if version > SCHEMA_VERSION => return Err(NewerSchemaError(version).into()),
+
+        version => return Err(anyhow!("unknown history schema version {}", version)),
+    };
+
+    if !reader.is_empty() {
+        return Err(anyhow!("unexpected data after the history entries"));
+    }
+
+    Ok(records)
+}
+
+/// write the `data` to a temporary file, and rename it to `path` after it is synced
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(TMP_SUFFIX);
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&tmp_path, path)?;
+
+    // make the rename durable
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::clipboard::Selection;
+
+    fn new_storage() -> (Storage, PathBuf) {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = env::temp_dir().join(format!(
+            "history_clipboard_test_{}_{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+
+        (open_storage(&dir), dir)
+    }
+
+    fn open_storage(dir: &Path) -> Storage {
+        let mut storage = Storage::with_dir(dir.to_path_buf()).unwrap();
+        storage.unlock("passphrase").unwrap();
+
+        storage
+    }
+
+    fn text_entry(text: &str) -> Entry {
+        Entry::from_content(Selection::Clipboard, text.to_string().into())
+    }
+
+    fn text_of(entry: &Entry) -> &str {
+        match &entry.content {
+            Content::Text(text) => text,
+            content => panic!("unexpected content {:?}", content),
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (storage, dir) = new_storage();
+
+        let mut pinned = text_entry("world");
+        pinned.pinned = true;
+        pinned.use_count = 3;
+        let saved = [text_entry("hello"), pinned];
+        storage.save(&saved).unwrap();
+
+        let entries = open_storage(&dir).load().unwrap();
+        assert_eq!(
+            entries.iter().map(text_of).collect::<Vec<_>>(),
+            ["hello", "world"]
+        );
+        assert_eq!(entries[0].targets.len(), text_entry("hello").targets.len());
+        assert!(!entries[0].pinned && entries[1].pinned);
+        for (entry, saved) in entries.iter().zip(&saved) {
+            assert!(entry.same(saved));
+            assert_eq!(entry.copied_at, saved.copied_at);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn save_image_once() {
+        let (storage, dir) = new_storage();
+
+        let mut png = vec![];
+        image::DynamicImage::new_rgba8(2, 2)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let raw = Arc::<[u8]>::from(png);
+        let sum = clipboard::md5_sum(&raw);
+        let img = ContentImage::decode(raw, image::ImageFormat::Png, sum).unwrap();
+        let entry = Entry::from_content(Selection::Clipboard, Content::Image(img));
+        storage.save([&entry]).unwrap();
+
+        // the other encodings are converted again when restoring
+        let blobs = fs::read_dir(dir.join(BLOBS_DIR_NAME)).unwrap().count();
+        assert_eq!(blobs, 1);
+        let entries = open_storage(&dir).load().unwrap();
+        assert_eq!(entries[0].targets.len(), entry.targets.len());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn survive_interrupted_write() {
+        let (storage, dir) = new_storage();
+
+        storage.save(&[text_entry("hello")]).unwrap();
+        // the new index is not renamed when crashing
+        fs::write(dir.join("history.tmp"), b"HCLP").unwrap();
+
+        let entries = open_storage(&dir).load().unwrap();
+        assert_eq!(entries.iter().map(text_of).collect::<Vec<_>>(), ["hello"]);
+        assert!(!dir.join("history.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keep_newer_schema() {
+        let (storage, dir) = new_storage();
+
+        let mut index = MAGIC.to_vec();
+        index.extend_from_slice(&(SCHEMA_VERSION + 1).to_le_bytes());
+        fs::write(dir.join(INDEX_NAME), &index).unwrap();
+
+        assert!(storage.load().unwrap_err().is::<NewerSchemaError>());
+        assert_eq!(fs::read(dir.join(INDEX_NAME)).unwrap(), index);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn encrypt_history() {
+        let (storage, dir) = new_storage();
+
+        storage.save(&[text_entry("secret text")]).unwrap();
+
+        let mut files = vec![dir.join(INDEX_NAME)];
+        for dir_entry in fs::read_dir(dir.join(BLOBS_DIR_NAME)).unwrap() {
+            files.push(dir_entry.unwrap().path());
+        }
+        for file in files {
+            let data = fs::read(file).unwrap();
+            assert!(!data.windows(6).any(|window| window == b"secret"));
+        }
+
+        let mut storage = Storage::with_dir(dir.clone()).unwrap();
+        assert_eq!(storage.prompt(), Prompt::Passphrase);
+        assert!(storage.load().is_err());
+        assert!(storage.unlock("wrong").is_err());
+        assert!(storage.is_locked());
+
+        storage.unlock("passphrase").unwrap();
+        let entries = storage.load().unwrap();
+        assert_eq!(
+            entries.iter().map(text_of).collect::<Vec<_>>(),
+            ["secret text"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_plain_history() {
+        let (storage, dir) = new_storage();
+
+        // the layout written by the older versions
+        let entry = text_entry("hello");
+        let sums = entry
+            .targets
+            .iter()
+            .map(|target| {
+                let data = target.data.get();
+                let sum = clipboard::md5_sum(&data);
+                fs::write(dir.join(BLOBS_DIR_NAME).join(hex(&sum)), &data).unwrap();
+
+                sum
+            })
+            .collect::<Vec<_>>();
+
+        let mut writer = Writer::default();
+        writer.put_raw(MAGIC);
+        writer.put_u32(1);
+        writer.put_u32(1);
+        writer.put_entry(&entry, &entry.targets.iter().collect::<Vec<_>>(), &sums);
+        let mut index = writer.into_inner();
+        // the version 1 has no entry flags, ids, times and use count
+        index.truncate(index.len() - (1 + 3 * 8 + 4));
+        fs::write(dir.join(INDEX_NAME), index).unwrap();
+
+        let entries = storage.load().unwrap();
+        assert_eq!(entries.iter().map(text_of).collect::<Vec<_>>(), ["hello"]);
+
+        storage.save(&entries).unwrap();
+        assert!(fs::read(dir.join(INDEX_NAME))
+            .unwrap()
+            .starts_with(SEALED_MAGIC));
+        for sum in sums {
+            assert!(!dir.join(BLOBS_DIR_NAME).join(hex(&sum)).exists());
+        }
+
+        let entries = open_storage(&dir).load().unwrap();
+        assert_eq!(entries.iter().map(text_of).collect::<Vec<_>>(), ["hello"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pass_entries() {
+        let entries = [text_entry("hello"), text_entry("world")];
+
+        let data = encode_entries(&entries);
+        let decoded = decode_entries(&data).unwrap();
+        assert_eq!(
+            decoded.iter().map(text_of).collect::<Vec<_>>(),
+            ["hello", "world"]
+        );
+        assert_eq!(decoded[0].targets.len(), entries[0].targets.len());
+        assert!(decoded[0].same(&entries[0]));
+
+        assert!(decode_entries(&data[..data.len() - 1]).is_err());
+    }
+}